@@ -1,24 +1,75 @@
 #![allow(clippy::all)]
-#![feature(asm)]
 #![no_builtins]
-#![feature(optin_builtin_traits)]
 #![no_std]
 
 use core::result::Result::{Ok, Err};
 use pi::timer;
-use pi::uart;
+use pi::console::{self, println};
 use pi::gpio;
+use std::io::{self, Error, ErrorKind, Read, Write};
 use std::mem;
-use std::xmodem::Xmodem;
+use std::xmodem::{Block, Xmodem, XmodemConfig, Progress};
 
 const BINARY_START_ADDR: usize = 0x80000;
 const BOOTLOADER_START_ADDR: usize = 0x4000000;
+const PAGE_SIZE: usize = 0x1000;
+
+/// Retry and timeout policy of the kernel transfer. Packets are streamed if
+/// the sender supports it. There is no deadline for the whole transfer: at
+/// 115200 baud, a minute is only enough for about 690 KB. A stalled sender
+/// is caught by the byte timeout instead: its packets time out until the
+/// retries run out, or right away when streaming, and the handshake starts
+/// over.
+const XMODEM_CONFIG: XmodemConfig = XmodemConfig {
+    packet_retries: 10,
+    handshake_retries: 3,
+    streaming: true,
+    byte_timeout: 750,
+    deadline: None,
+};
 
 fn jump_to(addr: *mut u8) -> ! {
+    #[cfg(target_arch = "aarch64")]
     unsafe {
-        asm!("br $0" : : "r"(addr as usize));
-        loop { asm!("nop" :::: "volatile")  }
+        core::arch::asm!("br {}", in(reg) addr as usize, options(noreturn));
+    }
+
+    #[cfg(not(target_arch = "aarch64"))]
+    unreachable!("can't jump to {:p} on the host", addr)
+}
+
+/// Returns the start of the page following `address`, or `address` if it
+/// starts a page.
+fn next_page(address: usize) -> usize {
+    (address + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+/// Receives the kernel into the memory from `start` to `end` and returns its
+/// size. The kernel is either sent alone with plain XMODEM, or as the first
+/// file of a YMODEM batch, whose size is then exact. Any further file of a
+/// batch (e.g. an initrd) is loaded at the page following the previous one.
+fn receive_kernel<T, F>(receiver: &mut Xmodem<T, F>, start: usize, end: usize) -> io::Result<usize>
+    where T: Read + Write,
+          F: FnMut(Progress)
+{
+    // Room for a 1K packet.
+    let mut packet = [0u8; 1024];
+    let mut into = mem::MemWrite::new(start, end);
+    let kernel_size = match receiver.read_header_or_packet(&mut packet)? {
+        Block::Packet(n) => {
+            into.write_all(&packet[..n])?;
+            return Ok(n + receiver.receive_into(into, None)?);
+        }
+        Block::Header(Some(header)) => receiver.receive_into(into, header.size())?,
+        Block::Header(None) => return Err(Error::new(ErrorKind::UnexpectedEof, "empty batch")),
+    };
+
+    let mut start = next_page(start + kernel_size);
+    while let Some(header) = receiver.read_header()? {
+        let size = receiver.receive_into(mem::MemWrite::new(start, end), header.size())?;
+        start = next_page(start + size);
     }
+    Ok(kernel_size)
 }
 
 #[no_mangle]
@@ -26,19 +77,55 @@ pub unsafe extern "C" fn kmain() {
     // Turn on the light 1 second to show that the Pi is ready.
     // Then turn off the light.
     let mut gpio16 = gpio::Gpio::new(16).into_output();
+    // Printed once. A sender already waiting for the handshake skips it as
+    // line garbage, so it must not contain `C`, `G` or `NAK`.
+    println!("swiftOS boot loader: waiting for a kernel");
     loop {
         gpio16.set();
-        // open a uart to recieve new data
-        let mut mini_uart = uart::MiniUart::new();
-        mini_uart.set_read_timeout(750);
-        // mem write
-        let mem_write = mem::MemWrite::new(BINARY_START_ADDR, BOOTLOADER_START_ADDR);
-        // xmodem
-        match Xmodem::receive(mini_uart, mem_write){
-            Ok(_) => jump_to(BINARY_START_ADDR as *mut u8),
+        // xmodem or ymodem over the console uart, released before printing
+        let result = {
+            let mut console = console::CONSOLE.lock();
+            Xmodem::new_with_config(&mut *console, XMODEM_CONFIG, |_| ())
+                .and_then(|mut receiver| receive_kernel(&mut receiver, BINARY_START_ADDR, BOOTLOADER_START_ADDR))
+        };
+        match result {
+            Ok(size) => {
+                println!("Received a kernel of {} bytes", size);
+                jump_to(BINARY_START_ADDR as *mut u8)
+            }
             Err(_) => {},
         }
         gpio16.clear();
         timer::spin_sleep_ms(1000);
     }
 }
+
+#[cfg(test)]
+mod boot_loader_test {
+    use super::*;
+    use std::xmodem::channel::{self, Faults};
+
+    #[test]
+    fn test_receive_from_default_ttywrite() {
+        let mut kernel = [0u8; 3000];
+        for (i, byte) in kernel.iter_mut().enumerate() {
+            *byte = (i % 251) as u8 + 1;
+        }
+
+        // `ttywrite` sends with plain XMODEM by default, streaming if the
+        // receiver asks for it, with a 10 second timeout.
+        let (port, line) = channel::channel(0, Faults::default());
+        let ttywrite_config = XmodemConfig { streaming: true, byte_timeout: 10_000, ..XmodemConfig::default() };
+        let sender = channel::transmit(line, ttywrite_config, kernel);
+
+        let mut memory = [0u8; 2 * PAGE_SIZE];
+        let start = memory.as_mut_ptr() as usize;
+        let size = Xmodem::new_with_config(port, XMODEM_CONFIG, |_| ())
+            .and_then(|mut receiver| receive_kernel(&mut receiver, start, start + memory.len()))
+            .expect("kernel received");
+        assert_eq!(sender.result(), Ok(3000));
+        assert_eq!(size, 3072);
+        assert_eq!(&memory[..3000], &kernel[..]);
+        assert!(memory[3000..].iter().all(|b| *b == 0));
+    }
+}
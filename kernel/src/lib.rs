@@ -1,16 +1,19 @@
-#![feature(asm)]
 #![no_builtins]
-#![feature(optin_builtin_traits)]
 #![no_std]
 
 use pi::timer;
 use pi::gpio;
+use pi::console::println;
 
 #[no_mangle]
 pub unsafe extern "C" fn kmain() {
     // Turn on the light 3 seconds to show that the Pi is ready.
     // Then turn off the light 4 seconds.
     let mut gpio16 = gpio::Gpio::new(16).into_output();
+    // Locks use the single-core fallback of `std::sync::exclusive` while the
+    // MMU is off. Once it is turned on, call `exclusive::enable()` before
+    // starting the other cores or unmasking IRQs.
+    println!("swiftOS kernel started");
 
     loop {
         gpio16.set();
@@ -1,7 +1,75 @@
 /// The address where I/O peripherals are mapped to.
 pub const IO_BASE: usize = 0x3F000000;
 
+/// Where the register blocks of the peripherals are mapped.
+///
+/// Drivers find their registers through an `Mmio`. `Mmio::PI` is the Pi's
+/// physical layout; host tests bind the blocks to memory of their own, and
+/// check what the drivers write there through the drivers' volatile
+/// register wrappers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mmio {
+    /// The base address of the `GPIO` registers.
+    pub gpio: usize,
+    /// The base address of the auxiliary peripherals' registers: `AUXENB`
+    /// and the mini UART's.
+    pub aux: usize,
+    /// The base address of the ARM system timer registers.
+    pub timer: usize,
+}
+
+impl Mmio {
+    /// The peripherals of the Pi.
+    pub const PI: Mmio = Mmio {
+        gpio: IO_BASE + 0x200000,
+        aux: IO_BASE + 0x215000,
+        timer: IO_BASE + 0x3000,
+    };
+
+    /// Returns the register block of type `R` at `base`.
+    ///
+    /// # Safety
+    ///
+    /// `base` must point to memory laid out as `R` that outlives the
+    /// returned reference, and that is only accessed through volatile
+    /// wrappers.
+    #[inline(always)]
+    pub(crate) unsafe fn registers<R>(base: usize) -> &'static mut R {
+        &mut *(base as *mut R)
+    }
+}
+
 /// Generates `pub enums` with no variants for each `ident` passed in.
 pub macro states($($name:ident),*) {
     $(pub enum $name {  })*
 }
+
+#[cfg(test)]
+pub(crate) mod common_test {
+    use super::Mmio;
+
+    /// Memory standing in for the register blocks of the peripherals, large
+    /// enough for the drivers' `Registers`. Its bytes start cleared.
+    #[repr(C, align(8))]
+    pub(crate) struct Simulated {
+        gpio: [u32; 64],
+        aux: [u32; 32],
+        timer: [u32; 8],
+    }
+
+    impl Simulated {
+        pub(crate) fn new() -> Simulated {
+            Simulated { gpio: [0; 64], aux: [0; 32], timer: [0; 8] }
+        }
+
+        /// Returns the blocks bound to `self`. The drivers created over them
+        /// must not outlive `self`.
+        pub(crate) fn mmio(&mut self) -> Mmio {
+            Mmio {
+                gpio: self.gpio.as_mut_ptr() as usize,
+                aux: self.aux.as_mut_ptr() as usize,
+                timer: self.timer.as_mut_ptr() as usize,
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use core::fmt;
+use std::io::*;
+use std::mutex::Mutex;
+use std::sync::Lazy;
+use crate::uart::MiniUart;
+
+/// The global console: the mini UART.
+pub struct Console {
+    inner: MiniUart,
+}
+
+impl Console {
+    /// Returns the console over a newly initialized mini UART.
+    fn new() -> Console {
+        Console { inner: MiniUart::new() }
+    }
+
+    /// Returns the mini UART.
+    fn inner(&mut self) -> &mut MiniUart {
+        &mut self.inner
+    }
+}
+
+impl Read for Console {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.inner().read(buf)
+    }
+}
+
+impl Write for Console {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.inner().write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner().flush()
+    }
+}
+
+impl ReadTimeout for Console {
+    fn set_read_timeout(&mut self, milliseconds: u32) -> Result<()> {
+        ReadTimeout::set_read_timeout(self.inner(), milliseconds)
+    }
+}
+
+/// Writes `\n` as `\r\n`, as expected by serial terminals.
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.write_all(b"\r\n").map_err(|_| fmt::Error)?;
+            }
+            self.write_all(line.as_bytes()).map_err(|_| fmt::Error)?;
+        }
+        Ok(())
+    }
+}
+
+/// The console shared by the kernel and the boot loader. The mini UART is
+/// initialized on first use.
+pub static CONSOLE: Lazy<Mutex<Console>> = Lazy::new(|| Mutex::new(Console::new()));
+
+/// Writes `args` to the console. Used by the `print!` family of macros.
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    let mut console = CONSOLE.lock();
+    let _ = fmt::Write::write_fmt(&mut *console, args);
+}
+
+/// Prints to the console.
+pub macro print($($arg:tt)*) {
+    $crate::console::_print(format_args!($($arg)*))
+}
+
+/// Prints to the console, with a newline.
+pub macro println {
+    () => ($crate::console::_print(format_args!("\n"))),
+    ($fmt:expr) => ($crate::console::_print(format_args!(concat!($fmt, "\n")))),
+    ($fmt:expr, $($arg:tt)*) => ($crate::console::_print(format_args!(concat!($fmt, "\n"), $($arg)*)))
+}
+
+/// Prints to the console, with a newline. The Pi has no separate error
+/// stream, so this is the same as `println!`.
+pub macro eprintln($($arg:tt)*) {
+    $crate::console::println!($($arg)*)
+}
@@ -7,33 +7,50 @@
 /// timer::spin_sleep_ms(100);
 
 use core::marker::PhantomData;
-use crate::common::{IO_BASE, states};
+use crate::common::{Mmio, states};
 use crate::volatile::prelude::*;
-use crate::volatile::{Volatile, WriteVolatile, ReadVolatile, Reserved};
-
-/// An alternative GPIO function.
-#[repr(u8)]
-pub enum Function {
-    Input = 0b000,
-    Output = 0b001,
-    Alt0 = 0b100,
-    Alt1 = 0b101,
-    Alt2 = 0b110,
-    Alt3 = 0b111,
-    Alt4 = 0b011,
-    Alt5 = 0b010
+use crate::volatile::{Volatile, WriteVolatile, ReadVolatile, Reserved, field_enum, register};
+
+field_enum! {
+    /// An alternative GPIO function.
+    pub enum Function: u32 {
+        Input = 0b000,
+        Output = 0b001,
+        Alt0 = 0b100,
+        Alt1 = 0b101,
+        Alt2 = 0b110,
+        Alt3 = 0b111,
+        Alt4 = 0b011,
+        Alt5 = 0b010
+    }
+}
+
+register! {
+    /// A function select register, `GPFSELn`, of 10 pins.
+    struct Fsel(u32) {
+        /// The function of the pin `10 * n + index`.
+        function, set_function: [10] 2, 0 => Function;
+    }
+}
+
+register! {
+    /// A register of one bit per pin: `GPSETn`, `GPCLRn` or `GPLEVn`.
+    struct Pins(u32) {
+        /// The bit of the pin `32 * n + index`.
+        pin, set_pin: [32] 0, 0 => bool;
+    }
 }
 
 #[repr(C)]
 #[allow(non_snake_case)]
 struct Registers {
-    FSEL: [Volatile<u32>; 6],
+    FSEL: [Volatile<Fsel>; 6],
     __r0: Reserved<u32>,
-    SET: [WriteVolatile<u32>; 2],
+    SET: [WriteVolatile<Pins>; 2],
     __r1: Reserved<u32>,
-    CLR: [WriteVolatile<u32>; 2],
+    CLR: [WriteVolatile<Pins>; 2],
     __r2: Reserved<u32>,
-    LEV: [ReadVolatile<u32>; 2],
+    LEV: [ReadVolatile<Pins>; 2],
     __r3: Reserved<u32>,
     EDS: [Volatile<u32>; 2],
     __r4: Reserved<u32>,
@@ -70,9 +87,6 @@ pub struct Gpio<State> {
     _state: PhantomData<State>
 }
 
-/// The base address of the `GPIO` registers.
-const GPIO_BASE: usize = IO_BASE + 0x200000;
-
 impl<T> Gpio<T> {
     /// Transitions `self` to state `S`, consuming `self` and returning a new
     /// `Gpio` instance in state `S`. This method should _never_ be exposed to
@@ -94,12 +108,27 @@ impl Gpio<Uninitialized> {
     ///
     /// Panics if `pin` > `53`.
     pub fn new(pin: u8) -> Gpio<Uninitialized> {
+        unsafe { Gpio::with_mmio(Mmio::PI, pin) }
+    }
+
+    /// Returns a new `GPIO` structure for pin number `pin` of the `GPIO`
+    /// registers of `mmio`.
+    ///
+    /// # Safety
+    ///
+    /// `mmio.gpio` must point to the `GPIO` registers, or to memory standing
+    /// in for them, that outlive the returned pin.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pin` > `53`.
+    pub unsafe fn with_mmio(mmio: Mmio, pin: u8) -> Gpio<Uninitialized> {
         if pin > 53 {
             panic!("Gpio::new(): pin {} exceeds maximum of 53", pin);
         }
 
         Gpio {
-            registers: unsafe { &mut *(GPIO_BASE as *mut Registers) },
+            registers: Mmio::registers(mmio.gpio),
             pin: pin,
             _state: PhantomData
         }
@@ -108,12 +137,8 @@ impl Gpio<Uninitialized> {
     /// Enables the alternative function `function` for `self`. Consumes self
     /// and returns a `Gpio` structure in the `Alt` state.
     pub fn into_alt(self, function: Function) -> Gpio<Alt> {
-        let function = function as u32;
-        let register_offset: usize = self.pin as usize / 10;
-        let gpio_offset: u8 = self.pin % 10;
-        let current = self.registers.FSEL[register_offset].read();
-        let after_set = (current | (function<<(3*gpio_offset)))  & (!((7-function)<<(3*gpio_offset)));
-        self.registers.FSEL[register_offset].write(after_set);
+        let pin = self.pin as usize;
+        self.registers.FSEL[pin / 10].modify(|fsel| fsel.set_function(pin % 10, function));
         Gpio::<Alt>{
             pin: self.pin,
             registers: self.registers,
@@ -137,16 +162,14 @@ impl Gpio<Uninitialized> {
 impl Gpio<Output> {
     /// Sets (turns on) the pin.
     pub fn set(&mut self) {
-        let register_offset: usize = self.pin as usize / 32;
-        let gpio_offset: u8 = self.pin % 32;
-        self.registers.SET[register_offset].write(1<<gpio_offset);
+        let pin = self.pin as usize;
+        self.registers.SET[pin / 32].write(Pins::default().set_pin(pin % 32, true));
     }
 
     /// Clears (turns off) the pin.
     pub fn clear(&mut self) {
-        let register_offset: usize = self.pin as usize / 32;
-        let gpio_offset: u8 = self.pin % 32;
-        self.registers.CLR[register_offset].write(1<<gpio_offset);
+        let pin = self.pin as usize;
+        self.registers.CLR[pin / 32].write(Pins::default().set_pin(pin % 32, true));
     }
 }
 
@@ -154,9 +177,61 @@ impl Gpio<Input> {
     /// Reads the pin's value. Returns `true` if the level is high and `false`
     /// if the level is low.
     pub fn level(&mut self) -> bool {
-        let register_offset: usize = self.pin as usize / 32;
-        let gpio_offset: u8 = self.pin % 32;
-        let current = self.registers.LEV[register_offset].read();
-        (current & (1<<gpio_offset)) != 0
+        let pin = self.pin as usize;
+        self.registers.LEV[pin / 32].read().pin(pin % 32)
+    }
+}
+
+#[cfg(test)]
+mod gpio_test {
+    use super::*;
+    use crate::common::common_test::Simulated;
+    use core::ptr;
+
+    #[test]
+    fn test_into_alt() {
+        let mut simulated = Simulated::new();
+        let mmio = simulated.mmio();
+        let registers: &mut Registers = unsafe { Mmio::registers(mmio.gpio) };
+        registers.FSEL[1].write(Fsel::from_bits(0x3fff_ffff));
+
+        unsafe { Gpio::with_mmio(mmio, 14) }.into_alt(Function::Alt5);
+        // Only the bits 14..12 of pin 14 change, to 0b010.
+        assert_eq!(registers.FSEL[1].read().bits(), 0x3fff_afff);
+        assert_eq!(registers.FSEL[1].read().function(4), Some(Function::Alt5));
+
+        unsafe { Gpio::with_mmio(mmio, 14) }.into_output();
+        assert_eq!(registers.FSEL[1].read().function(4), Some(Function::Output));
+        assert_eq!(registers.FSEL[1].read().function(3), Some(Function::Alt3));
+    }
+
+    #[test]
+    fn test_set_clear() {
+        let mut simulated = Simulated::new();
+        let mmio = simulated.mmio();
+        let registers: &mut Registers = unsafe { Mmio::registers(mmio.gpio) };
+        let written = |register: &WriteVolatile<Pins>| unsafe { ptr::read_volatile(register.ptr()) };
+
+        let mut pin = unsafe { Gpio::with_mmio(mmio, 40) }.into_output();
+        assert_eq!(registers.FSEL[4].read().function(0), Some(Function::Output));
+        pin.set();
+        assert_eq!(written(&registers.SET[1]).bits(), 1 << 8);
+        assert_eq!(written(&registers.CLR[1]).bits(), 0);
+        pin.clear();
+        assert_eq!(written(&registers.CLR[1]).bits(), 1 << 8);
+        assert_eq!(written(&registers.SET[0]).bits(), 0);
+    }
+
+    #[test]
+    fn test_level() {
+        let mut simulated = Simulated::new();
+        let mmio = simulated.mmio();
+        let registers: &mut Registers = unsafe { Mmio::registers(mmio.gpio) };
+        let mut pin = unsafe { Gpio::with_mmio(mmio, 5) }.into_input();
+        assert!(!pin.level());
+
+        let level = Pins::default().set_pin(5, true);
+        unsafe { ptr::write_volatile(registers.LEV[0].ptr() as *mut Pins, level) };
+        assert!(pin.level());
     }
 }
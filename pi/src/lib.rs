@@ -1,6 +1,4 @@
 #![feature(core_intrinsics)]
-#![feature(const_fn)]
-#![feature(asm)]
 #![feature(decl_macro)]
 #![feature(never_type)]
 #![no_std]
@@ -11,3 +9,4 @@ pub mod timer;
 pub mod uart;
 pub mod gpio;
 pub mod common;
+pub mod console;
@@ -1,30 +1,51 @@
-use crate::common::IO_BASE;
+use crate::common::Mmio;
 use std::volatile::prelude::*;
-use std::volatile::{Volatile, ReadVolatile};
+use std::volatile::{Volatile, ReadVolatile, Unique, register};
+use std::sync::Lazy;
 
-/// The base address for the ARM system timer registers.
-const TIMER_REG_BASE: usize = IO_BASE + 0x3000;
+register! {
+    /// The control/status register, `CS`. Writing a 1 to a match bit clears
+    /// it.
+    struct Cs(u32) {
+        /// Whether the counter matched `COMPARE[index]`.
+        matched, set_matched: [4] 0, 0 => bool;
+    }
+}
 
 #[repr(C)]
 #[allow(non_snake_case)]
 struct Registers {
-    CS: Volatile<u32>,
-    CLO: ReadVolatile<u32>,
-    CHI: ReadVolatile<u32>,
-    COMPARE: [Volatile<u32>; 4]
+    CS: Unique<Volatile<Cs>>,
+    CLO: Unique<ReadVolatile<u32>>,
+    CHI: Unique<ReadVolatile<u32>>,
+    COMPARE: [Unique<Volatile<u32>>; 4]
 }
 
-/// The Raspberry Pi ARM system timer.
+/// The Raspberry Pi ARM system timer. Its instance over the Pi's registers is
+/// `TIMER`.
 pub struct Timer {
     registers: &'static mut Registers
 }
 
+/// The system timer, shared by all cores.
+pub static TIMER: Lazy<Timer> = Lazy::new(Timer::new);
+
 impl Timer {
     /// Returns a new instance of `Timer`.
-    pub fn new() -> Timer {
-        Timer {
-            registers: unsafe { &mut *(TIMER_REG_BASE as *mut Registers) },
-        }
+    fn new() -> Timer {
+        unsafe { Timer::with_mmio(Mmio::PI) }
+    }
+
+    /// Returns a timer over the system timer registers of `mmio`. Only
+    /// `TIMER` is over the Pi's; others are for devices that keep their own,
+    /// such as simulated ones.
+    ///
+    /// # Safety
+    ///
+    /// `mmio.timer` must point to the system timer registers, or to memory
+    /// standing in for them, that outlive the returned timer.
+    pub unsafe fn with_mmio(mmio: Mmio) -> Timer {
+        Timer { registers: Mmio::registers(mmio.timer) }
     }
 
     /// Reads the system timer's counter and returns the 64-bit counter value.
@@ -38,13 +59,12 @@ impl Timer {
 
 /// Returns the current time in microseconds.
 pub fn current_time() -> u64 {
-    let tm = Timer::new();
-    tm.read()
+    TIMER.read()
 }
 
 /// Spins until `us` microseconds have passed.
 pub fn spin_sleep_us(us: u64) {
-    let tm = Timer::new();
+    let tm = &*TIMER;
     let t0 = tm.read();
     loop{
         let t1 = tm.read();
@@ -56,7 +76,7 @@ pub fn spin_sleep_us(us: u64) {
 
 /// Spins until `ms` milliseconds have passed.
 pub fn spin_sleep_ms(ms: u64) {
-    let tm = Timer::new();
+    let tm = &*TIMER;
     let t0 = tm.read();
     let us = ms*1000;
     loop{
@@ -66,3 +86,23 @@ pub fn spin_sleep_ms(ms: u64) {
         }
     }
 }
+
+#[cfg(test)]
+mod timer_test {
+    use super::*;
+    use crate::common::common_test::Simulated;
+    use core::ptr;
+
+    #[test]
+    fn test_read() {
+        let mut simulated = Simulated::new();
+        let mmio = simulated.mmio();
+        let timer = unsafe { Timer::with_mmio(mmio) };
+        unsafe {
+            // `CLO` and `CHI` follow `CS`.
+            ptr::write_volatile((mmio.timer + 4) as *mut u32, 0x89ab_cdef);
+            ptr::write_volatile((mmio.timer + 8) as *mut u32, 0x1);
+        }
+        assert_eq!(timer.read(), 0x1_89ab_cdef);
+    }
+}
@@ -1,19 +1,61 @@
 use crate::timer;
-use crate::common::IO_BASE;
+use crate::common::Mmio;
 use crate::gpio::{Gpio, Function};
 use std::io::*;
 use std::volatile::*;
 
-/// The base address for the `MU` registers.
-const MU_REG_BASE: usize = IO_BASE + 0x215040;
-/// The `AUXENB` register from page 9 of the BCM2837 documentation.
-const AUX_ENABLES: *mut Volatile<u8> = (IO_BASE + 0x215004) as *mut Volatile<u8>;
+/// The offset of the `MU` registers from the auxiliary peripherals' base.
+const MU_REG_OFFSET: usize = 0x40;
+/// The offset of the `AUXENB` register from page 9 of the BCM2837
+/// documentation.
+const AUX_ENABLES_OFFSET: usize = 0x4;
 
-/// Enum representing bit fields of the `AUX_MU_LSR_REG` register.
-#[repr(u8)]
-enum LsrStatus {
-    DataReady = 1,
-    TxAvailable = 1 << 5,
+register! {
+    /// The auxiliary enables register, `AUXENB`.
+    struct AuxEnables(u8) {
+        mini_uart, set_mini_uart: 0, 0 => bool;
+        spi1, set_spi1: 1, 1 => bool;
+        spi2, set_spi2: 2, 2 => bool;
+    }
+}
+
+field_enum! {
+    /// The number of data bits of the mini UART.
+    enum DataSize: u8 {
+        SevenBit = 0b00,
+        EightBit = 0b11,
+    }
+}
+
+register! {
+    /// The line control register, `AUX_MU_LCR_REG`.
+    struct Lcr(u8) {
+        data_size, set_data_size: 1, 0 => DataSize;
+        /// Pulls the transmit line low.
+        break_, set_break: 6, 6 => bool;
+        /// Gives access to the baud rate registers.
+        dlab, set_dlab: 7, 7 => bool;
+    }
+}
+
+register! {
+    /// The line status register, `AUX_MU_LSR_REG`.
+    struct Lsr(u8) {
+        data_ready, _: 0, 0 => bool;
+        receiver_overrun, _: 1, 1 => bool;
+        /// The transmit FIFO can accept at least one byte.
+        tx_available, _: 5, 5 => bool;
+        /// The transmit FIFO is empty and the transmitter is idle.
+        tx_idle, _: 6, 6 => bool;
+    }
+}
+
+register! {
+    /// The extra control register, `AUX_MU_CNTL_REG`.
+    struct Cntl(u8) {
+        receiver_enable, set_receiver_enable: 0, 0 => bool;
+        transmitter_enable, set_transmitter_enable: 1, 1 => bool;
+    }
 }
 
 #[repr(C)]
@@ -25,48 +67,65 @@ struct Registers {
     _r1: [Reserved<u8>; 3],
     MU_IIR: Volatile<u8>,
     _r2: [Reserved<u8>; 3],
-    MU_LCR: Volatile<u8>,
+    MU_LCR: Volatile<Lcr>,
     _r3: [Reserved<u8>; 3],
     MU_MCR: Volatile<u8>,
     _r4: [Reserved<u8>; 3],
-    MU_LSR: ReadVolatile<u8>,
+    MU_LSR: ReadVolatile<Lsr>,
     _r5: [Reserved<u8>; 3],
     MU_MSR: ReadVolatile<u8>,
     _r6: [Reserved<u8>; 3],
     MU_SCRATCH: Volatile<u8>,
     _r7: [Reserved<u8>; 3],
-    MU_CNTL: Volatile<u8>,
+    MU_CNTL: Volatile<Cntl>,
     _r8: [Reserved<u8>; 3],
     MU_STAT: ReadVolatile<u32>,
     MU_BAUD: Volatile<u16>,
 }
 
-/// The Raspberry Pi's "mini UART".
+/// The Raspberry Pi's "mini UART". Its only instance is owned by
+/// `console::CONSOLE`.
 pub struct MiniUart {
     registers: &'static mut Registers,
+    /// Returns the current time in microseconds.
+    clock: fn() -> u64,
     timeout: Option<u32>,
 }
 
 impl MiniUart {
-    pub fn new() -> MiniUart {
-        let registers = unsafe {
-            // Enable the mini UART as an auxiliary device.
-            (*AUX_ENABLES).or_mask(1);
-            &mut *(MU_REG_BASE as *mut Registers)
-        };
+    /// Enables and configures the mini UART. Only the console calls this, so
+    /// that the hardware is initialized exactly once.
+    pub(crate) fn new() -> MiniUart {
+        unsafe { MiniUart::with_mmio(Mmio::PI, timer::current_time) }
+    }
+
+    /// Enables and configures the mini UART of `mmio` and the `GPIO` pins it
+    /// uses. Timeouts are measured with `clock`, which returns the current
+    /// time in microseconds: `timer::current_time` on the Pi.
+    ///
+    /// # Safety
+    ///
+    /// The auxiliary peripherals' and `GPIO` registers of `mmio` must outlive
+    /// the returned mini UART, which must be the only one over them.
+    pub(crate) unsafe fn with_mmio(mmio: Mmio, clock: fn() -> u64) -> MiniUart {
+        // Enable the mini UART as an auxiliary device.
+        let enables: &mut Volatile<AuxEnables> = Mmio::registers(mmio.aux + AUX_ENABLES_OFFSET);
+        enables.modify(|aux| aux.set_mini_uart(true));
+        let registers: &'static mut Registers = Mmio::registers(mmio.aux + MU_REG_OFFSET);
         // 1. Set GPIO 14 as TXD1
-        Gpio::new(14).into_alt(Function::Alt5);
+        Gpio::with_mmio(mmio, 14).into_alt(Function::Alt5);
         // 2. Set GPIO 15 as RDXD1
-        Gpio::new(15).into_alt(Function::Alt5);
+        Gpio::with_mmio(mmio, 15).into_alt(Function::Alt5);
         // 3. Set data size as 8 bits
-        registers.MU_LCR.or_mask(0b11);
+        registers.MU_LCR.modify(|lcr| lcr.set_data_size(DataSize::EightBit));
         // 4. Set BAUD rate to ~115200
         registers.MU_BAUD.write(270 as u16);
         // 5. Enable
-        registers.MU_CNTL.or_mask(0b11);
+        registers.MU_CNTL.modify(|cntl| cntl.set_receiver_enable(true).set_transmitter_enable(true));
 
         MiniUart {
-            registers: registers,
+            registers,
+            clock,
             timeout: None,
         }
     }
@@ -78,7 +137,7 @@ impl MiniUart {
 
     /// Returns `true` if there is at least one byte ready to be read.
     pub fn has_byte(&self) -> bool {
-        self.registers.MU_LSR.has_mask(LsrStatus::DataReady as u8)
+        self.registers.MU_LSR.read().data_ready()
     }
 
     /// Do nothing. Stop when there is at least one byte to read.
@@ -89,59 +148,134 @@ impl MiniUart {
             }
         }
     }
+
+    /// Waits until `status` is true of the line status register. Returns
+    /// `TimedOut` if the timeout passes first.
+    fn wait_for(&self, status: fn(&Lsr) -> bool) -> Result<()> {
+        let t0 = (self.clock)();
+        loop {
+            if status(&self.registers.MU_LSR.read()) {
+                return Ok(());
+            }
+            if let Some(timeout) = self.timeout {
+                if (self.clock)() - t0 > (timeout as u64) * 1000 {
+                    return Err(Error::new(ErrorKind::TimedOut, "mini UART timed out"));
+                }
+            }
+        }
+    }
 }
 
 
+impl ReadTimeout for MiniUart {
+    fn set_read_timeout(&mut self, milliseconds: u32) -> Result<()> {
+        MiniUart::set_read_timeout(self, milliseconds);
+        Ok(())
+    }
+}
+
 impl Read for MiniUart {
-    fn read_byte(& self) -> Result<u8, ErrorKind>{
-        match self.timeout {
-            Some(timeout) => {
-                let t0 = timer::current_time();
-                loop{
-                    if self.has_byte(){
-                        return Ok(self.registers.MU_IO.read());
-                    }
-                    let t1 = timer::current_time();
-                    if t1 - t0 > (timeout as u64) * 1000{
-                        return Err(ErrorKind::TimedOut);
-                    }
-                }
-            },
-            None => {
-                loop{
-                    if self.has_byte(){
-                        return Ok(self.registers.MU_IO.read());
-                    }
-                }
-            }
+    /// Waits for a first byte, up to the read timeout, then reads the bytes
+    /// that are already received.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.wait_for(Lsr::data_ready)?;
+        let mut n = 0;
+        while n < buf.len() && self.has_byte() {
+            buf[n] = self.registers.MU_IO.read();
+            n += 1;
         }
+        Ok(n)
     }
 }
 
 impl Write for MiniUart {
-    fn write_byte(&mut self, byte: u8) -> Result<u8, ErrorKind>{
-        match self.timeout {
-            Some(timeout) => {
-                let t0 = timer::current_time();
-                loop{
-                    if self.registers.MU_LSR.has_mask(LsrStatus::TxAvailable as u8){
-                        break;
-                    }
-                    let t1 = timer::current_time();
-                    if t1-t0 > (timeout as u64) * 1000{
-                        return Err(ErrorKind::TimedOut);
-                    }
-                }
-            },
-            None => {
-                loop{
-                    if self.registers.MU_LSR.has_mask(LsrStatus::TxAvailable as u8){
-                        break;
-                    }
-                }
+    /// Writes the bytes of `buf` as the transmitter accepts them. Stops early
+    /// if it doesn't accept one before the timeout.
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        for (i, byte) in buf.iter().enumerate() {
+            match self.wait_for(Lsr::tx_available) {
+                Ok(()) => self.registers.MU_IO.write(*byte),
+                Err(e) if i == 0 => return Err(e),
+                Err(_) => return Ok(i),
             }
         }
-        self.registers.MU_IO.write(byte);
-        Ok(byte)
+        Ok(buf.len())
+    }
+
+    /// Waits until the transmitter is idle.
+    fn flush(&mut self) -> Result<()> {
+        self.wait_for(Lsr::tx_idle)
+    }
+}
+
+#[cfg(test)]
+mod uart_test {
+    use super::*;
+    use crate::common::common_test::Simulated;
+    use core::ptr;
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    /// A clock that doesn't move.
+    fn stopped() -> u64 {
+        0
+    }
+
+    #[test]
+    fn test_init() {
+        let mut simulated = Simulated::new();
+        let mmio = simulated.mmio();
+        let _uart = unsafe { MiniUart::with_mmio(mmio, stopped) };
+
+        let enables: &mut Volatile<AuxEnables> = unsafe { Mmio::registers(mmio.aux + AUX_ENABLES_OFFSET) };
+        assert!(enables.read().mini_uart());
+        let registers: &mut Registers = unsafe { Mmio::registers(mmio.aux + MU_REG_OFFSET) };
+        assert_eq!(registers.MU_LCR.read().data_size(), Some(DataSize::EightBit));
+        assert_eq!(registers.MU_BAUD.read(), 270);
+        let cntl = registers.MU_CNTL.read();
+        assert!(cntl.receiver_enable() && cntl.transmitter_enable());
+        // `GPFSEL1` has pins 14 and 15 at bits 14..12 and 17..15, set to
+        // `Alt5`.
+        let fsel1 = unsafe { ptr::read_volatile((mmio.gpio + 4) as *const u32) };
+        assert_eq!(fsel1, 0b010_010 << 12);
+    }
+
+    #[test]
+    fn test_read_write() {
+        let mut simulated = Simulated::new();
+        let mmio = simulated.mmio();
+        let mut uart = unsafe { MiniUart::with_mmio(mmio, stopped) };
+        let registers: &mut Registers = unsafe { Mmio::registers(mmio.aux + MU_REG_OFFSET) };
+        let lsr = registers.MU_LSR.ptr() as *mut Lsr;
+        let set_lsr = |bits: u8| unsafe { ptr::write_volatile(lsr, Lsr::from_bits(bits)) };
+
+        set_lsr(1 << 5);
+        assert!(!uart.has_byte());
+        assert_eq!(uart.write(b"hi").ok(), Some(2));
+        assert_eq!(registers.MU_IO.read(), b'i');
+
+        registers.MU_IO.write(b'x');
+        set_lsr(1 << 0);
+        let mut buf = [0; 3];
+        assert_eq!(uart.read(&mut buf).ok(), Some(3));
+        assert_eq!(&buf, b"xxx");
+    }
+
+    #[test]
+    fn test_read_timeout() {
+        /// A clock that moves a millisecond each time it is read.
+        fn ticking() -> u64 {
+            static NOW: AtomicU64 = AtomicU64::new(0);
+            NOW.fetch_add(1000, Ordering::Relaxed)
+        }
+
+        let mut simulated = Simulated::new();
+        let mut uart = unsafe { MiniUart::with_mmio(simulated.mmio(), ticking) };
+        uart.set_read_timeout(5);
+        let mut buf = [0; 1];
+        let error = uart.read(&mut buf).expect_err("no byte received");
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
     }
 }
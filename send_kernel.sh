@@ -3,4 +3,6 @@ ttywrite="./ttywrite/target/release/ttywrite"
 kernel="./kernel/build/kernel.bin"
 tty_path="/dev/ttyUSB0"
 
-$ttywrite "-i" $kernel $tty_path
+# The boot loader also accepts plain XMODEM, but a YMODEM header gives it the
+# exact size of the kernel.
+$ttywrite "--ymodem" "-i" $kernel $tty_path
@@ -0,0 +1,206 @@
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::str::{self, FromStr};
+
+use crate::string_like;
+pub use crate::string_like::{Error, StringLike};
+
+/// A UTF-8 string holding up to `N` bytes inline.
+///
+/// `ArrayString` is the owning counterpart of `StackString`: its storage is
+/// an array of `N` bytes rather than a borrowed slice, so it can be returned
+/// from functions and stored in statics. Appending past the capacity fails
+/// with an `Error` rather than panicking, and so does `write!`. The rest of
+/// the `String` API is provided by the `StringLike` trait.
+#[derive(Clone)]
+pub struct ArrayString<const N: usize> {
+    storage: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> ArrayString<N> {
+    /// Constructs a new, empty `ArrayString`.
+    pub const fn new() -> ArrayString<N> {
+        ArrayString { storage: [0; N], len: 0 }
+    }
+
+    /// Returns the number of bytes this string can hold, `N`.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Extracts a string slice containing the entire string.
+    pub fn as_str(&self) -> &str {
+        unsafe { str::from_utf8_unchecked(&self.storage[..self.len]) }
+    }
+
+    /// Extracts a mutable string slice containing the entire string.
+    pub fn as_mut_str(&mut self) -> &mut str {
+        unsafe { str::from_utf8_unchecked_mut(&mut self.storage[..self.len]) }
+    }
+
+    /// Appends `string` to the back of this string. See
+    /// `StringLike::push_str`.
+    pub fn push_str(&mut self, string: &str) -> Result<(), Error> {
+        let end = self.len + string.len();
+        if end > N {
+            return Err(Error::Full);
+        }
+
+        self.storage[self.len..end].copy_from_slice(string.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+
+    /// Shortens this string to `len` bytes. See `StringLike::truncate`.
+    pub fn truncate(&mut self, len: usize) -> Result<(), Error> {
+        if len >= self.len {
+            return Ok(());
+        }
+        if !self.is_char_boundary(len) {
+            return Err(Error::NotCharBoundary);
+        }
+
+        self.len = len;
+        Ok(())
+    }
+}
+
+impl<const N: usize> StringLike for ArrayString<N> {
+    fn capacity(&self) -> usize {
+        N
+    }
+
+    fn push_str(&mut self, string: &str) -> Result<(), Error> {
+        ArrayString::push_str(self, string)
+    }
+
+    fn truncate(&mut self, len: usize) -> Result<(), Error> {
+        ArrayString::truncate(self, len)
+    }
+}
+
+impl<const N: usize> FromStr for ArrayString<N> {
+    type Err = Error;
+
+    /// Returns a copy of `string`, or `Full` if it's longer than `N` bytes.
+    fn from_str(string: &str) -> Result<ArrayString<N>, Error> {
+        let mut copy = ArrayString::new();
+        copy.push_str(string)?;
+        Ok(copy)
+    }
+}
+
+impl<const N: usize> Default for ArrayString<N> {
+    fn default() -> ArrayString<N> {
+        ArrayString::new()
+    }
+}
+
+impl<const N: usize> Deref for ArrayString<N> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> DerefMut for ArrayString<N> {
+    fn deref_mut(&mut self) -> &mut str {
+        self.as_mut_str()
+    }
+}
+
+impl<const N: usize> AsRef<str> for ArrayString<N> {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> fmt::Write for ArrayString<N> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        self.push_str(string).map_err(|_| fmt::Error)
+    }
+
+    fn write_fmt(&mut self, args: fmt::Arguments) -> fmt::Result {
+        string_like::write_fmt(self, args)
+    }
+}
+
+impl<const N: usize> fmt::Display for ArrayString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> fmt::Debug for ArrayString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> PartialEq for ArrayString<N> {
+    fn eq(&self, other: &ArrayString<N>) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl<const N: usize> Eq for ArrayString<N> {}
+
+impl<'a, const N: usize> PartialEq<&'a str> for ArrayString<N> {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl<const N: usize> PartialEq<str> for ArrayString<N> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+#[cfg(test)]
+mod array_string_test {
+    use super::*;
+    use core::fmt::Write;
+
+    static EMPTY: ArrayString<4> = ArrayString::new();
+
+    fn path(dir: &str, name: &str) -> Result<ArrayString<12>, fmt::Error> {
+        let mut path = ArrayString::new();
+        write!(path, "/{}/{}", dir, name)?;
+        Ok(path)
+    }
+
+    #[test]
+    fn test_owned() {
+        assert!(EMPTY.is_empty());
+        assert_eq!(EMPTY.capacity(), 4);
+
+        let mut p = path("boot", "kernel").expect("fits");
+        assert_eq!(p, "/boot/kernel");
+        assert!(p.is_full());
+        assert_eq!(path("boot", "kernel8"), Err(fmt::Error));
+        let mut short = path("boot", "k").expect("fits");
+        assert_eq!(write!(short, "/{}", "initrd"), Err(fmt::Error));
+        assert_eq!(short, "/boot/k");
+
+        let mut name: ArrayString<8> = ArrayString::new();
+        p.split_off_into(6, &mut name).expect("fits");
+        assert_eq!((p.as_str(), name.as_str()), ("/boot/", "kernel"));
+        assert_eq!(p.clone(), p);
+    }
+
+    #[test]
+    fn test_from_str() {
+        let s: ArrayString<3> = "日".parse().expect("fits");
+        assert_eq!(s.len(), 3);
+        assert_eq!("日本".parse::<ArrayString<3>>(), Err(Error::Full));
+
+        let mut s = s;
+        assert_eq!(s.truncate(1), Err(Error::NotCharBoundary));
+        assert_eq!(s.pop(), Some('日'));
+        assert_eq!(s.pop(), None);
+        assert_eq!(s.remove(0), Err(Error::OutOfBounds));
+    }
+}
@@ -0,0 +1,301 @@
+use core::fmt;
+use core::mem::{ManuallyDrop, MaybeUninit};
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use core::slice;
+
+pub use crate::vec_like::{Drain, VecLike};
+
+/// A contiguous array type holding up to `N` elements inline.
+///
+/// `ArrayVec` is the owning counterpart of `StackVec`: its storage is an
+/// array of `N` elements rather than a borrowed slice, so it can be returned
+/// from functions and stored in statics. Like `StackVec`, it requires no
+/// memory allocation and `push` fails once it is full. The rest of the `Vec`
+/// API is provided by the `VecLike` trait.
+pub struct ArrayVec<T, const N: usize> {
+    storage: [MaybeUninit<T>; N],
+    len: usize,
+}
+
+impl<T, const N: usize> ArrayVec<T, N> {
+    /// Constructs a new, empty `ArrayVec`.
+    pub const fn new() -> ArrayVec<T, N> {
+        ArrayVec {
+            // An array of `MaybeUninit` needs no initialization.
+            storage: unsafe { MaybeUninit::<[MaybeUninit<T>; N]>::uninit().assume_init() },
+            len: 0,
+        }
+    }
+
+    /// Returns the number of elements this vector can hold, `N`.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of elements in the vector.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the vector contains no elements.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if the vector is at capacity.
+    pub const fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Extracts a slice containing the entire vector.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.storage.as_ptr() as *const T, self.len) }
+    }
+
+    /// Extracts a mutable slice of the entire vector.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.storage.as_mut_ptr() as *mut T, self.len) }
+    }
+
+    /// Appends `value` to the back of this vector if the vector is not full.
+    ///
+    /// # Error
+    ///
+    /// If this vector is full, `value` is returned in an `Err`.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(value);
+        }
+
+        self.storage[self.len] = MaybeUninit::new(value);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes the last element from this vector and returns it, or `None`
+    /// if it is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+
+        self.len -= 1;
+        Some(unsafe { self.storage[self.len].as_ptr().read() })
+    }
+
+    /// Shortens the vector, keeping the first `len` elements and dropping the
+    /// others. If `len` is greater than the vector's current length, this has
+    /// no effect.
+    pub fn truncate(&mut self, len: usize) {
+        while self.len > len {
+            self.len -= 1;
+            unsafe { ptr::drop_in_place(self.storage[self.len].as_mut_ptr()) };
+        }
+    }
+}
+
+impl<T, const N: usize> VecLike<T> for ArrayVec<T, N> {
+    fn capacity(&self) -> usize {
+        N
+    }
+
+    fn push(&mut self, value: T) -> Result<(), T> {
+        ArrayVec::push(self, value)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        ArrayVec::pop(self)
+    }
+
+    fn truncate(&mut self, len: usize) {
+        ArrayVec::truncate(self, len)
+    }
+}
+
+impl<T, const N: usize> Drop for ArrayVec<T, N> {
+    fn drop(&mut self) {
+        self.truncate(0);
+    }
+}
+
+impl<T, const N: usize> Default for ArrayVec<T, N> {
+    fn default() -> ArrayVec<T, N> {
+        ArrayVec::new()
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for ArrayVec<T, N> {
+    fn clone(&self) -> ArrayVec<T, N> {
+        let mut clone = ArrayVec::new();
+        for value in self.iter() {
+            let _ = clone.push(value.clone());
+        }
+        clone
+    }
+}
+
+impl<T, const N: usize> Deref for ArrayVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T, const N: usize> DerefMut for ArrayVec<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for ArrayVec<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq, const N: usize> PartialEq for ArrayVec<T, N> {
+    fn eq(&self, other: &ArrayVec<T, N>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: Eq, const N: usize> Eq for ArrayVec<T, N> {}
+
+/// By-value iterator over the elements of an `ArrayVec`.
+pub struct IntoIter<T, const N: usize> {
+    storage: [MaybeUninit<T>; N],
+    start: usize,
+    end: usize,
+}
+
+impl<T, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+
+        self.start += 1;
+        Some(unsafe { self.storage[self.start - 1].as_ptr().read() })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.end - self.start, Some(self.end - self.start))
+    }
+}
+
+impl<T, const N: usize> DoubleEndedIterator for IntoIter<T, N> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+
+        self.end -= 1;
+        Some(unsafe { self.storage[self.end].as_ptr().read() })
+    }
+}
+
+impl<T, const N: usize> Drop for IntoIter<T, N> {
+    fn drop(&mut self) {
+        for _ in self {}
+    }
+}
+
+impl<T, const N: usize> IntoIterator for ArrayVec<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+
+    fn into_iter(self) -> IntoIter<T, N> {
+        let this = ManuallyDrop::new(self);
+        // The elements are moved to the iterator, which drops them instead.
+        let storage = unsafe { ptr::read(&this.storage) };
+        IntoIter { storage, start: 0, end: this.len }
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a ArrayVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a mut ArrayVec<T, N> {
+    type Item = &'a mut T;
+    type IntoIter = slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> slice::IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod array_vec_test {
+    use super::*;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    static EMPTY: ArrayVec<u8, 4> = ArrayVec::new();
+
+    fn digits(n: u32) -> ArrayVec<u8, 10> {
+        let mut digits = ArrayVec::new();
+        let mut n = n;
+        loop {
+            digits.insert(0, (n % 10) as u8).expect("fits");
+            n /= 10;
+            if n == 0 {
+                return digits;
+            }
+        }
+    }
+
+    #[test]
+    fn test_owned() {
+        assert!(EMPTY.is_empty());
+        assert_eq!(EMPTY.capacity(), 4);
+
+        let mut v = digits(90210);
+        assert_eq!(&v[..], &[9, 0, 2, 1, 0]);
+        assert_eq!(v.clone(), v);
+        v.retain(|d| *d != 0);
+        assert_eq!(v.drain(1..).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(v.into_iter().collect::<Vec<_>>(), vec![9]);
+    }
+
+    #[test]
+    fn test_full() {
+        let mut v: ArrayVec<u8, 2> = ArrayVec::new();
+        v.extend_from_slice(&[1, 2]).expect("fits");
+        assert_eq!(v.push(3), Err(3));
+        assert_eq!(v.insert(0, 3), Err(3));
+        assert_eq!(v.swap_remove(0), 1);
+        assert_eq!(&v[..], &[2]);
+    }
+
+    #[test]
+    fn test_drop() {
+        let rc = Rc::new(());
+        {
+            let mut v: ArrayVec<Rc<()>, 4> = ArrayVec::new();
+            for _ in 0..4 {
+                v.push(rc.clone()).expect("fits");
+            }
+            assert!(v.push(rc.clone()).is_err());
+            assert_eq!(Rc::strong_count(&rc), 5);
+
+            v.remove(1);
+            v.drain(..1).next();
+            assert_eq!(Rc::strong_count(&rc), 3);
+
+            let mut iter = v.clone().into_iter();
+            assert!(iter.next_back().is_some());
+            assert_eq!(Rc::strong_count(&rc), 4);
+        }
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+}
@@ -1,4 +1,18 @@
+#[cfg(any(test, feature = "host"))]
+pub mod host;
+mod buffered;
+mod cursor;
+
+use core::cmp;
+use core::fmt;
+use core::result;
+use crate::stack_vec::StackVec;
+
+pub use self::buffered::{BufReader, BufWriter};
+pub use self::cursor::Cursor;
+
 /// io Error Kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ErrorKind {
     NotFound,
     PermissionDenied,
@@ -18,30 +32,487 @@ pub enum ErrorKind {
     Interrupted,
     Other,
     UnexpectedEof,
+    OutOfMemory,
+}
+
+impl ErrorKind {
+    /// Returns a short description of the error kind.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ErrorKind::NotFound => "entity not found",
+            ErrorKind::PermissionDenied => "permission denied",
+            ErrorKind::ConnectionRefused => "connection refused",
+            ErrorKind::ConnectionReset => "connection reset",
+            ErrorKind::ConnectionAborted => "connection aborted",
+            ErrorKind::NotConnected => "not connected",
+            ErrorKind::AddrInUse => "address in use",
+            ErrorKind::AddrNotAvailable => "address not available",
+            ErrorKind::BrokenPipe => "broken pipe",
+            ErrorKind::AlreadyExists => "entity already exists",
+            ErrorKind::WouldBlock => "operation would block",
+            ErrorKind::InvalidInput => "invalid input parameter",
+            ErrorKind::InvalidData => "invalid data",
+            ErrorKind::TimedOut => "timed out",
+            ErrorKind::WriteZero => "write zero",
+            ErrorKind::Interrupted => "operation interrupted",
+            ErrorKind::Other => "other error",
+            ErrorKind::UnexpectedEof => "unexpected end of file",
+            ErrorKind::OutOfMemory => "out of memory",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// io Error
+///
+/// An error of some `ErrorKind`, with an optional message telling what went
+/// wrong and an optional code reported by the device or the host system.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Error {
+    kind: ErrorKind,
+    message: Option<&'static str>,
+    code: Option<i32>,
+}
+
+/// Result type of the functions in this module.
+pub type Result<T> = result::Result<T, Error>;
+
+impl Error {
+    /// Returns an error of kind `kind` with the message `message`.
+    pub fn new(kind: ErrorKind, message: &'static str) -> Error {
+        Error { kind, message: Some(message), code: None }
+    }
+
+    /// Returns an error of kind `kind` with the code `code` reported by the
+    /// device or the host system.
+    pub fn from_code(kind: ErrorKind, code: i32) -> Error {
+        Error { kind, message: None, code: Some(code) }
+    }
+
+    /// Returns the kind of this error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns the message of this error, if any.
+    pub fn message(&self) -> Option<&'static str> {
+        self.message
+    }
+
+    /// Returns the code of this error, if any.
+    pub fn code(&self) -> Option<i32> {
+        self.code
+    }
 }
 
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error { kind, message: None, code: None }
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut debug = f.debug_struct("Error");
+        debug.field("kind", &self.kind);
+        if let Some(message) = self.message {
+            debug.field("message", &message);
+        }
+        if let Some(code) = self.code {
+            debug.field("code", &code);
+        }
+        debug.finish()
+    }
+}
+
+/// Formats as `kind: message (code N)`, leaving out the missing parts.
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(message) = self.message {
+            write!(f, ": {}", message)?;
+        }
+        if let Some(code) = self.code {
+            write!(f, " (code {})", code)?;
+        }
+        Ok(())
+    }
+}
 
 /// Read Trait
+///
+/// Mirrors `std::io::Read`: `read` pulls some bytes into a buffer and may
+/// return fewer than asked for. A return value of `0` for a non-empty buffer
+/// marks the end of the stream.
 pub trait Read {
-    fn read_byte(& self) -> Result<u8, ErrorKind>;
+    /// Pulls some bytes from this source into `buf`, returning how many bytes
+    /// were read. Blocks until at least one byte is available, the stream
+    /// ends or an error occurs.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
 
-    fn read(& self, buf: &mut [u8]) -> Result<usize, ErrorKind>{
-        let n = buf.len();
-        for byte in buf{
-            *byte = self.read_byte()?;
+    /// Reads a single byte. Returns `UnexpectedEof` at the end of the stream.
+    fn read_byte(&mut self) -> Result<u8> {
+        let mut byte = [0u8; 1];
+        self.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    /// Reads exactly `buf.len()` bytes into `buf`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `UnexpectedEof` if the stream ends before `buf` is filled, in
+    /// which case the contents of `buf` are unspecified. `Interrupted` reads
+    /// are retried.
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.read(buf) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(n) => { let tmp = buf; buf = &mut tmp[n..]; }
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
         }
-        Ok(n)
+
+        Ok(())
+    }
+
+    /// Reads until `buf` is full or the stream ends. Returns the number of
+    /// bytes read, which is less than `buf.len()` only at the end of the
+    /// stream. `Interrupted` reads are retried.
+    fn read_max(&mut self, mut buf: &mut [u8]) -> Result<usize> {
+        let start_len = buf.len();
+        while !buf.is_empty() {
+            match self.read(buf) {
+                Ok(0) => break,
+                Ok(n) => { let tmp = buf; buf = &mut tmp[n..]; }
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(start_len - buf.len())
+    }
+
+    /// Reads until the end of the stream, appending the bytes to `buf`.
+    /// Returns the number of bytes appended.
+    ///
+    /// # Errors
+    ///
+    /// Returns `OutOfMemory` if the stream has more bytes than `buf` can
+    /// hold. The bytes that fit are kept in `buf`.
+    fn read_to_end<'b>(&mut self, buf: &mut StackVec<'b, u8>) -> Result<usize> {
+        let start_len = buf.len();
+        let mut chunk = [0u8; 64];
+        loop {
+            // Once `buf` is full, a single byte is read to tell the end of
+            // the stream from an overflow.
+            let room = cmp::min(chunk.len(), buf.capacity() - buf.len());
+            let n = match self.read(&mut chunk[..cmp::max(room, 1)]) {
+                Ok(0) => return Ok(buf.len() - start_len),
+                Ok(n) => n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if n > room {
+                return Err(ErrorKind::OutOfMemory.into());
+            }
+            for byte in chunk[..n].iter() {
+                let _ = buf.push(*byte);
+            }
+        }
+    }
+
+    /// Returns a mutable reference to `self`, so that adapters can be used
+    /// without consuming it.
+    fn by_ref(&mut self) -> &mut Self where Self: Sized {
+        self
+    }
+
+    /// Returns an adapter reading at most `limit` bytes from `self`.
+    fn take(self, limit: u64) -> Take<Self> where Self: Sized {
+        Take { inner: self, limit }
+    }
+
+    /// Returns an adapter reading from `self` until its end, then from
+    /// `next`.
+    fn chain<R: Read>(self, next: R) -> Chain<Self, R> where Self: Sized {
+        Chain { first: self, second: next, done_first: false }
     }
 }
 
 /// Write Trait
+///
+/// Mirrors `std::io::Write`: `write` may accept fewer bytes than given, and
+/// buffered bytes only reach their destination once `flush` returns.
 pub trait Write {
-    fn write_byte(&mut self, byte: u8) -> Result<u8, ErrorKind>;
+    /// Writes some bytes of `buf`, returning how many were written. A return
+    /// value of `0` for a non-empty `buf` means no more bytes can be written.
+    fn write(&mut self, buf: &[u8]) -> Result<usize>;
+
+    /// Blocks until every written byte has reached its destination.
+    fn flush(&mut self) -> Result<()>;
+
+    /// Writes a single byte.
+    fn write_byte(&mut self, byte: u8) -> Result<()> {
+        self.write_all(&[byte])
+    }
 
-    fn write(&mut self, buf: & [u8]) -> Result<usize, ErrorKind>{
-        for byte in buf{
-            self.write_byte(*byte)?;
+    /// Writes all of `buf`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WriteZero` if a write accepts no bytes. `Interrupted` writes
+    /// are retried.
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => buf = &buf[n..],
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
         }
-        Ok(buf.len())
+
+        Ok(())
+    }
+
+    /// Returns a mutable reference to `self`, so that it can be used without
+    /// being consumed.
+    fn by_ref(&mut self) -> &mut Self where Self: Sized {
+        self
+    }
+}
+
+/// Read Timeout Trait
+pub trait ReadTimeout {
+    /// Sets the read timeout to `milliseconds` milliseconds. Reads that time
+    /// out return `TimedOut`.
+    fn set_read_timeout(&mut self, milliseconds: u32) -> Result<()>;
+}
+
+/// Position to seek to, relative to the start, the end or the current
+/// position of a stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    End(i64),
+    Current(i64),
+}
+
+/// Seek Trait
+///
+/// Mirrors `std::io::Seek`: moves the position of a stream, which may be
+/// past its end.
+pub trait Seek {
+    /// Moves the position to `pos` and returns the new position from the
+    /// start of the stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidInput` if the position would be negative.
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64>;
+}
+
+impl<T: Read + ?Sized> Read for &mut T {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        (**self).read(buf)
+    }
+
+    fn read_byte(&mut self) -> Result<u8> {
+        (**self).read_byte()
+    }
+}
+
+impl<T: Write + ?Sized> Write for &mut T {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        (**self).write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+
+    fn write_byte(&mut self, byte: u8) -> Result<()> {
+        (**self).write_byte(byte)
+    }
+}
+
+impl<T: Seek + ?Sized> Seek for &mut T {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        (**self).seek(pos)
+    }
+}
+
+impl<T: ReadTimeout + ?Sized> ReadTimeout for &mut T {
+    fn set_read_timeout(&mut self, milliseconds: u32) -> Result<()> {
+        (**self).set_read_timeout(milliseconds)
+    }
+}
+
+/// Reading from a slice consumes its front.
+impl Read for &[u8] {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = cmp::min(buf.len(), self.len());
+        let (front, back) = self.split_at(n);
+        buf[..n].copy_from_slice(front);
+        *self = back;
+        Ok(n)
+    }
+}
+
+/// Writing to a slice fills its front. Writes accept no bytes once the slice
+/// is full.
+impl Write for &mut [u8] {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let n = cmp::min(buf.len(), self.len());
+        let (front, back) = ::core::mem::take(self).split_at_mut(n);
+        front.copy_from_slice(&buf[..n]);
+        *self = back;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Adapter reading at most a fixed number of bytes from the inner reader.
+/// Returned by `Read::take`.
+pub struct Take<T> {
+    inner: T,
+    limit: u64,
+}
+
+impl<T> Take<T> {
+    /// Returns the number of bytes left to read before the limit.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Returns a mutable reference to the inner reader.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Returns the inner reader.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Read> Read for Take<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.limit == 0 {
+            return Ok(0);
+        }
+
+        let max = cmp::min(buf.len() as u64, self.limit) as usize;
+        let n = self.inner.read(&mut buf[..max])?;
+        self.limit -= n as u64;
+        Ok(n)
+    }
+}
+
+/// Adapter reading from a first reader until its end, then from a second
+/// one. Returned by `Read::chain`.
+pub struct Chain<T, U> {
+    first: T,
+    second: U,
+    done_first: bool,
+}
+
+impl<T, U> Chain<T, U> {
+    /// Returns the two inner readers.
+    pub fn into_inner(self) -> (T, U) {
+        (self.first, self.second)
+    }
+}
+
+impl<T: Read, U: Read> Read for Chain<T, U> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if !self.done_first {
+            match self.first.read(buf)? {
+                0 if !buf.is_empty() => self.done_first = true,
+                n => return Ok(n),
+            }
+        }
+
+        self.second.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod io_test {
+    use super::*;
+
+    #[test]
+    fn test_read_exact() {
+        let mut input = &[1u8, 2, 3][..];
+        let mut buf = [0u8; 2];
+        input.read_exact(&mut buf).expect("read okay");
+        assert_eq!(buf, [1, 2]);
+        assert_eq!(input.read_byte(), Ok(3));
+        assert_eq!(input.read_exact(&mut buf), Err(ErrorKind::UnexpectedEof.into()));
+    }
+
+    #[test]
+    fn test_write_all() {
+        let mut storage = [0u8; 4];
+        let mut output = &mut storage[..];
+        output.write_all(&[1, 2, 3]).expect("write okay");
+        assert_eq!(output.write_all(&[4, 5]), Err(ErrorKind::WriteZero.into()));
+        assert_eq!(storage, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_read_to_end() {
+        let mut storage = [0u8; 8];
+        let mut buf = StackVec::new(&mut storage);
+        let mut input = &[1u8; 8][..];
+        assert_eq!(input.read_to_end(&mut buf), Ok(8));
+        assert_eq!(buf.len(), 8);
+
+        let mut storage = [0u8; 8];
+        let mut buf = StackVec::new(&mut storage);
+        let mut input = &[1u8; 9][..];
+        assert_eq!(input.read_to_end(&mut buf), Err(ErrorKind::OutOfMemory.into()));
+        assert_eq!(&buf[..], &[1u8; 8][..]);
+    }
+
+    #[test]
+    fn test_error_format() {
+        use std::format;
+
+        let e = Error::new(ErrorKind::InvalidData, "expected ACK or NAK");
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert_eq!(format!("{}", e), "invalid data: expected ACK or NAK");
+        assert_eq!(format!("{:?}", e),
+                   "Error { kind: InvalidData, message: \"expected ACK or NAK\" }");
+
+        let e = Error::from_code(ErrorKind::Other, 5);
+        assert_eq!(format!("{}", e), "other error (code 5)");
+        assert_eq!(format!("{}", Error::from(ErrorKind::TimedOut)), "timed out");
+    }
+
+    #[test]
+    fn test_take_and_chain() {
+        let mut input = (&[1u8, 2, 3][..]).chain(&[4u8, 5][..]).take(4);
+        let mut buf = [0u8; 8];
+        assert_eq!(input.read_max(&mut buf), Ok(4));
+        assert_eq!(&buf[..4], &[1, 2, 3, 4]);
+        assert_eq!(input.limit(), 0);
+
+        let (_, mut rest) = input.into_inner().into_inner();
+        assert_eq!(rest.read_byte(), Ok(5));
+
+        let mut input = &[1u8, 2, 3][..];
+        assert_eq!(input.by_ref().take(2).read_max(&mut buf), Ok(2));
+        assert_eq!(input, &[3][..]);
     }
 }
@@ -0,0 +1,257 @@
+use core::{cmp, mem, ptr};
+
+use super::{Error, ErrorKind, Read, Result, Write};
+
+/// Adds buffering to a reader.
+///
+/// Like `StackVec`, `BufReader` requires no memory allocation: its buffer is
+/// a user-supplied slice. Reads at least as large as the buffer bypass it
+/// when it is empty.
+pub struct BufReader<'a, R> {
+    inner: R,
+    buf: &'a mut [u8],
+    pos: usize,
+    filled: usize,
+}
+
+impl<'a, R: Read> BufReader<'a, R> {
+    /// Returns a reader buffering reads from `inner` in `buf`.
+    pub fn new(inner: R, buf: &'a mut [u8]) -> BufReader<'a, R> {
+        BufReader { inner, buf, pos: 0, filled: 0 }
+    }
+
+    /// Returns the buffered bytes, reading from the inner reader first if
+    /// there are none. An empty slice marks the end of the stream.
+    pub fn fill_buf(&mut self) -> Result<&[u8]> {
+        if self.pos == self.filled {
+            self.filled = self.inner.read(self.buf)?;
+            self.pos = 0;
+        }
+
+        Ok(&self.buf[self.pos..self.filled])
+    }
+
+    /// Marks `amount` buffered bytes as read. `amount` is capped to the
+    /// number of buffered bytes.
+    pub fn consume(&mut self, amount: usize) {
+        self.pos = cmp::min(self.pos + amount, self.filled);
+    }
+}
+
+impl<'a, R> BufReader<'a, R> {
+    /// Returns the buffered bytes, without reading from the inner reader.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.pos..self.filled]
+    }
+
+    /// Returns a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner reader. Reading from it
+    /// directly skips the buffered bytes.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Returns the inner reader. The buffered bytes are lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<'a, R: Read> Read for BufReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.pos == self.filled && buf.len() >= self.buf.len() {
+            return self.inner.read(buf);
+        }
+
+        let n = {
+            let available = self.fill_buf()?;
+            let n = cmp::min(available.len(), buf.len());
+            buf[..n].copy_from_slice(&available[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+/// Adds buffering to a writer.
+///
+/// Like `StackVec`, `BufWriter` requires no memory allocation: its buffer is
+/// a user-supplied slice. Bytes reach the inner writer once the buffer is
+/// full, on `flush`, or when the `BufWriter` is dropped; errors on drop are
+/// ignored. Writes at least as large as the buffer bypass it.
+pub struct BufWriter<'a, W: Write> {
+    inner: W,
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a, W: Write> BufWriter<'a, W> {
+    /// Returns a writer buffering writes to `inner` in `buf`.
+    pub fn new(inner: W, buf: &'a mut [u8]) -> BufWriter<'a, W> {
+        BufWriter { inner, buf, len: 0 }
+    }
+
+    /// Returns the buffered bytes.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Returns a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner writer. Writing to it
+    /// directly bypasses the buffered bytes.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Writes the buffered bytes, then returns the inner writer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing the buffered bytes fails. The `BufWriter`
+    /// is dropped, trying to write them once more.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.write_buf()?;
+        // `self` has no bytes left to write on drop.
+        let inner = unsafe { ptr::read(&self.inner) };
+        mem::forget(self);
+        Ok(inner)
+    }
+
+    /// Writes the buffered bytes to the inner writer. The bytes that were
+    /// written are removed from the buffer even if a later write fails.
+    fn write_buf(&mut self) -> Result<()> {
+        let mut written = 0;
+        let mut result = Ok(());
+        while written < self.len {
+            match self.inner.write(&self.buf[written..self.len]) {
+                Ok(0) => {
+                    result = Err(Error::new(ErrorKind::WriteZero, "failed to write the buffered data"));
+                    break;
+                }
+                Ok(n) => written += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        self.len -= written;
+        for i in 0..self.len {
+            self.buf[i] = self.buf[written + i];
+        }
+        result
+    }
+}
+
+impl<'a, W: Write> Write for BufWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if self.len + buf.len() > self.buf.len() {
+            self.write_buf()?;
+        }
+
+        if buf.len() >= self.buf.len() {
+            return self.inner.write(buf);
+        }
+
+        let n = cmp::min(buf.len(), self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&buf[..n]);
+        self.len += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.write_buf()?;
+        self.inner.flush()
+    }
+}
+
+impl<'a, W: Write> Drop for BufWriter<'a, W> {
+    fn drop(&mut self) {
+        let _ = self.write_buf();
+    }
+}
+
+#[cfg(test)]
+mod buffered_test {
+    use super::*;
+
+    /// A reader returning at most 3 bytes per read, counting its reads.
+    struct Slow<'a>(&'a [u8], usize);
+
+    impl<'a> Read for Slow<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            self.1 += 1;
+            let n = cmp::min(3, buf.len());
+            self.0.read(&mut buf[..n])
+        }
+    }
+
+    #[test]
+    fn test_buf_reader() {
+        let mut storage = [0u8; 4];
+        let mut reader = BufReader::new(Slow(&[1, 2, 3, 4, 5], 0), &mut storage);
+        assert_eq!(reader.read_byte(), Ok(1));
+        assert_eq!(reader.buffer(), &[2, 3]);
+        assert_eq!(reader.read_byte(), Ok(2));
+        assert_eq!(reader.get_ref().1, 1);
+
+        let mut buf = [0u8; 8];
+        assert_eq!(reader.read_max(&mut buf), Ok(3));
+        assert_eq!(&buf[..3], &[3, 4, 5]);
+        assert_eq!(reader.fill_buf(), Ok(&[][..]));
+    }
+
+    #[test]
+    fn test_buf_reader_bypass() {
+        let mut storage = [0u8; 2];
+        let mut reader = BufReader::new(&[1u8, 2, 3][..], &mut storage);
+        let mut buf = [0u8; 3];
+        assert_eq!(reader.read(&mut buf), Ok(3));
+        assert!(reader.buffer().is_empty());
+    }
+
+    #[test]
+    fn test_buf_writer() {
+        let mut output = [0u8; 8];
+        {
+            let mut storage = [0u8; 4];
+            let mut writer = BufWriter::new(&mut output[..], &mut storage);
+            writer.write_all(&[1, 2, 3]).expect("write okay");
+            assert_eq!(writer.buffer(), &[1, 2, 3]);
+            writer.write_all(&[4, 5]).expect("write okay");
+            assert_eq!(writer.buffer(), &[4, 5]);
+            writer.write_all(&[6]).expect("write okay");
+        }
+        assert_eq!(&output[..6], &[1, 2, 3, 4, 5, 6]);
+
+        let mut output = [0u8; 8];
+        let mut storage = [0u8; 4];
+        let mut writer = BufWriter::new(&mut output[..], &mut storage);
+        writer.write_all(&[1, 2, 3, 4, 5]).expect("write okay");
+        writer.write_byte(6).expect("write okay");
+        let rest = writer.into_inner().expect("flush okay");
+        assert_eq!(rest.len(), 2);
+        assert_eq!(&output[..6], &[1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_buf_writer_full() {
+        let mut output = [0u8; 2];
+        let mut storage = [0u8; 4];
+        let mut writer = BufWriter::new(&mut output[..], &mut storage);
+        writer.write_all(&[1, 2, 3]).expect("buffered");
+        assert_eq!(writer.flush().map_err(|e| e.kind()), Err(ErrorKind::WriteZero));
+        assert_eq!(writer.buffer(), &[3]);
+    }
+}
@@ -0,0 +1,161 @@
+use core::cmp;
+
+use super::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+
+/// Wraps an in-memory buffer to implement `Read`, `Seek` and, for mutable
+/// slices, `Write`.
+///
+/// Reads and writes start at the current position, which advances past the
+/// bytes read or written. Writes never grow the buffer: they accept no bytes
+/// once the position reaches its end.
+#[derive(Debug, Clone, Default)]
+pub struct Cursor<T> {
+    inner: T,
+    pos: u64,
+}
+
+impl<T> Cursor<T> {
+    /// Returns a cursor over `inner`, at position 0.
+    pub fn new(inner: T) -> Cursor<T> {
+        Cursor { inner, pos: 0 }
+    }
+
+    /// Returns the buffer.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Returns a reference to the buffer.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the buffer.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Returns the current position.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Sets the current position to `pos`, which may be past the end of the
+    /// buffer.
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+}
+
+impl<T: AsRef<[u8]>> Cursor<T> {
+    /// Returns the bytes from the current position to the end of the buffer.
+    fn remaining(&self) -> &[u8] {
+        let inner = self.inner.as_ref();
+        let start = cmp::min(self.pos, inner.len() as u64) as usize;
+        &inner[start..]
+    }
+}
+
+impl<T: AsRef<[u8]>> Read for Cursor<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.remaining().read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<T: AsRef<[u8]>> Seek for Cursor<T> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => {
+                self.pos = offset;
+                return Ok(offset);
+            }
+            SeekFrom::End(offset) => (self.inner.as_ref().len() as u64, offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+
+        let pos = if offset >= 0 {
+            base.checked_add(offset as u64)
+        } else {
+            base.checked_sub(offset.wrapping_neg() as u64)
+        };
+        match pos {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            None => Err(Error::new(ErrorKind::InvalidInput, "seek to a negative or overflowing position")),
+        }
+    }
+}
+
+impl Write for Cursor<&mut [u8]> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let start = cmp::min(self.pos, self.inner.len() as u64) as usize;
+        let n = (&mut self.inner[start..]).write(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod cursor_test {
+    use super::*;
+
+    #[test]
+    fn test_read_and_seek() {
+        let mut cursor = Cursor::new(&[1u8, 2, 3, 4][..]);
+        let mut buf = [0u8; 3];
+        assert_eq!(cursor.read(&mut buf), Ok(3));
+        assert_eq!(cursor.position(), 3);
+
+        assert_eq!(cursor.seek(SeekFrom::Current(-2)), Ok(1));
+        assert_eq!(cursor.read_byte(), Ok(2));
+        assert_eq!(cursor.seek(SeekFrom::End(-1)), Ok(3));
+        assert_eq!(cursor.read_byte(), Ok(4));
+        assert_eq!(cursor.read(&mut buf), Ok(0));
+
+        assert_eq!(cursor.seek(SeekFrom::Start(10)), Ok(10));
+        assert_eq!(cursor.read(&mut buf), Ok(0));
+        let e = cursor.seek(SeekFrom::End(-5)).expect_err("negative position");
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        assert_eq!(cursor.position(), 10);
+    }
+
+    #[test]
+    fn test_write() {
+        let mut storage = [0u8; 4];
+        {
+            let mut cursor = Cursor::new(&mut storage[..]);
+            cursor.write_all(&[1, 2]).expect("write okay");
+            cursor.set_position(3);
+            assert_eq!(cursor.write(&[3, 4]), Ok(1));
+            assert_eq!(cursor.write(&[4]), Ok(0));
+
+            cursor.set_position(0);
+            assert_eq!(cursor.read_byte(), Ok(1));
+            cursor.write_byte(5).expect("write okay");
+        }
+        assert_eq!(storage, [1, 5, 0, 3]);
+    }
+
+    #[test]
+    fn test_write_past_end() {
+        let mut storage = [0u8; 4];
+        let mut cursor = Cursor::new(&mut storage[..]);
+        assert_eq!(cursor.seek(SeekFrom::End(6)), Ok(10));
+        assert_eq!(cursor.write(&[1, 2]), Ok(0));
+        assert_eq!(cursor.position(), 10);
+
+        cursor.set_position(1);
+        assert_eq!(cursor.write(&[]), Ok(0));
+        assert_eq!(cursor.position(), 1);
+        assert_eq!(cursor.write(&[1, 2]), Ok(2));
+        assert_eq!(cursor.position(), 3);
+    }
+}
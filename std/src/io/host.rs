@@ -0,0 +1,166 @@
+//! Adapters between this module's traits and Rust's `std::io`, for use of the
+//! protocols in this crate on the host. Available with the `host` feature.
+
+use core::time::Duration;
+use host::io;
+use host::error;
+
+use super::{Error, ErrorKind, Read, ReadTimeout, Result, Write};
+
+/// Adapter implementing `Read`, `Write` and, if possible, `ReadTimeout` over
+/// a host stream implementing `std::io::Read` and `std::io::Write`.
+pub struct HostIo<T>(T);
+
+impl<T> HostIo<T> {
+    /// Returns an adapter over the host stream `inner`.
+    pub fn new(inner: T) -> HostIo<T> {
+        HostIo(inner)
+    }
+
+    /// Returns a reference to the host stream.
+    pub fn get_ref(&self) -> &T {
+        &self.0
+    }
+
+    /// Returns a mutable reference to the host stream.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+
+    /// Returns the host stream.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: io::Read> Read for HostIo<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        Ok(self.0.read(buf)?)
+    }
+}
+
+impl<T: io::Write> Write for HostIo<T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        Ok(self.0.write(buf)?)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.0.flush()?)
+    }
+}
+
+/// Host streams whose reads can time out with an error of kind `TimedOut`,
+/// such as serial ports.
+pub trait HostTimeout {
+    /// Sets the read timeout to `timeout`.
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+}
+
+impl<T: HostTimeout> ReadTimeout for HostIo<T> {
+    fn set_read_timeout(&mut self, milliseconds: u32) -> Result<()> {
+        Ok(self.0.set_timeout(Duration::from_millis(milliseconds as u64))?)
+    }
+}
+
+impl From<io::ErrorKind> for ErrorKind {
+    fn from(kind: io::ErrorKind) -> ErrorKind {
+        match kind {
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+            io::ErrorKind::ConnectionRefused => ErrorKind::ConnectionRefused,
+            io::ErrorKind::ConnectionReset => ErrorKind::ConnectionReset,
+            io::ErrorKind::ConnectionAborted => ErrorKind::ConnectionAborted,
+            io::ErrorKind::NotConnected => ErrorKind::NotConnected,
+            io::ErrorKind::AddrInUse => ErrorKind::AddrInUse,
+            io::ErrorKind::AddrNotAvailable => ErrorKind::AddrNotAvailable,
+            io::ErrorKind::BrokenPipe => ErrorKind::BrokenPipe,
+            io::ErrorKind::AlreadyExists => ErrorKind::AlreadyExists,
+            io::ErrorKind::WouldBlock => ErrorKind::WouldBlock,
+            io::ErrorKind::InvalidInput => ErrorKind::InvalidInput,
+            io::ErrorKind::InvalidData => ErrorKind::InvalidData,
+            io::ErrorKind::TimedOut => ErrorKind::TimedOut,
+            io::ErrorKind::WriteZero => ErrorKind::WriteZero,
+            io::ErrorKind::Interrupted => ErrorKind::Interrupted,
+            io::ErrorKind::UnexpectedEof => ErrorKind::UnexpectedEof,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl From<ErrorKind> for io::ErrorKind {
+    fn from(kind: ErrorKind) -> io::ErrorKind {
+        match kind {
+            ErrorKind::NotFound => io::ErrorKind::NotFound,
+            ErrorKind::PermissionDenied => io::ErrorKind::PermissionDenied,
+            ErrorKind::ConnectionRefused => io::ErrorKind::ConnectionRefused,
+            ErrorKind::ConnectionReset => io::ErrorKind::ConnectionReset,
+            ErrorKind::ConnectionAborted => io::ErrorKind::ConnectionAborted,
+            ErrorKind::NotConnected => io::ErrorKind::NotConnected,
+            ErrorKind::AddrInUse => io::ErrorKind::AddrInUse,
+            ErrorKind::AddrNotAvailable => io::ErrorKind::AddrNotAvailable,
+            ErrorKind::BrokenPipe => io::ErrorKind::BrokenPipe,
+            ErrorKind::AlreadyExists => io::ErrorKind::AlreadyExists,
+            ErrorKind::WouldBlock => io::ErrorKind::WouldBlock,
+            ErrorKind::InvalidInput => io::ErrorKind::InvalidInput,
+            ErrorKind::InvalidData => io::ErrorKind::InvalidData,
+            ErrorKind::TimedOut => io::ErrorKind::TimedOut,
+            ErrorKind::WriteZero => io::ErrorKind::WriteZero,
+            ErrorKind::Interrupted => io::ErrorKind::Interrupted,
+            ErrorKind::Other => io::ErrorKind::Other,
+            ErrorKind::UnexpectedEof => io::ErrorKind::UnexpectedEof,
+            ErrorKind::OutOfMemory => io::ErrorKind::Other,
+        }
+    }
+}
+
+/// The message of a host error is dropped, since it isn't `'static`. Its OS
+/// error code, if any, is kept as the code.
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        let kind = ErrorKind::from(error.kind());
+        match error.raw_os_error() {
+            Some(code) => Error::from_code(kind, code),
+            None => Error::from(kind),
+        }
+    }
+}
+
+/// An error with a message or a code is wrapped in the host error, which then
+/// displays it; a bare error becomes a host error of the matching kind.
+impl From<Error> for io::Error {
+    fn from(error: Error) -> io::Error {
+        let kind = io::ErrorKind::from(error.kind());
+        match (error.message(), error.code()) {
+            (None, None) => io::Error::from(kind),
+            _ => io::Error::new(kind, error),
+        }
+    }
+}
+
+impl From<ErrorKind> for io::Error {
+    fn from(kind: ErrorKind) -> io::Error {
+        io::Error::from(io::ErrorKind::from(kind))
+    }
+}
+
+impl error::Error for Error {}
+
+#[cfg(test)]
+mod host_test {
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn test_host_error() {
+        let e = io::Error::from(Error::new(ErrorKind::InvalidData, "expected EOT"));
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(e.to_string(), "invalid data: expected EOT");
+
+        let e = io::Error::from(Error::from(ErrorKind::TimedOut));
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+
+        let e = Error::from(io::Error::from_raw_os_error(2));
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+        assert_eq!(e.code(), Some(2));
+    }
+}
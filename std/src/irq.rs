@@ -0,0 +1,50 @@
+/// Masking and unmasking of the current core's interrupts.
+///
+/// `IrqMutex` goes through this trait rather than the hardware so that its
+/// logic can be tested on the host with a fake implementation.
+pub trait Interrupts {
+    /// The saved interrupt mask state.
+    type State: Copy;
+
+    /// Masks IRQs and FIQs and returns the mask state from before the call.
+    fn save_and_mask() -> Self::State;
+
+    /// Restores a mask state returned by `save_and_mask`.
+    fn restore(state: Self::State);
+}
+
+/// The AArch64 interrupt mask bits in the `DAIF` register.
+///
+/// On other architectures, such as the host running tests, there are no
+/// interrupts to mask and this does nothing.
+pub struct Daif;
+
+impl Interrupts for Daif {
+    type State = u64;
+
+    #[inline(always)]
+    fn save_and_mask() -> u64 {
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            let daif: u64;
+            core::arch::asm!("mrs {}, DAIF", out(reg) daif, options(nomem, nostack));
+            // `DAIFSet` takes the D, A, I and F bits as bits 3 to 0.
+            core::arch::asm!("msr DAIFSet, #3", options(nostack));
+            daif
+        }
+
+        #[cfg(not(target_arch = "aarch64"))]
+        0
+    }
+
+    #[inline(always)]
+    fn restore(state: u64) {
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            core::arch::asm!("msr DAIF, {}", in(reg) state, options(nostack));
+        }
+
+        #[cfg(not(target_arch = "aarch64"))]
+        let _ = state;
+    }
+}
@@ -1,11 +1,25 @@
 #![feature(decl_macro)]
-#![feature(optin_builtin_traits)]
-#![no_std]
+#![feature(negative_impls)]
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(any(test, feature = "host"))]
+extern crate std as host;
 
 pub mod io;
 pub mod stack_vec;
+pub mod array_vec;
+pub mod vec_like;
+pub mod stack_string;
+pub mod array_string;
+pub mod string_like;
+pub mod ring_buffer;
+pub mod spsc;
 pub mod volatile;
 pub mod xmodem;
 pub mod mutex;
+pub mod irq;
+pub mod sync;
+#[cfg(not(any(test, feature = "host")))]
 pub mod panic;
 pub mod mem;
+pub mod line_editor;
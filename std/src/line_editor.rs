@@ -0,0 +1,466 @@
+//! A line editor for serial terminals, with a command history.
+//!
+//! Lines are read from a stream implementing `io::Read` and echoed to the
+//! same stream through `io::Write`, such as the console UART. The editor
+//! understands the keys of a VT100-compatible terminal:
+//!
+//!   * Backspace deletes the byte before the cursor, `Delete` the one under
+//!     it, and `Ctrl-U` the whole line.
+//!   * The left and right arrows, `Home` and `End` move the cursor.
+//!   * The up and down arrows recall older and newer lines of the history.
+//!   * `Ctrl-C` cancels the line.
+//!   * `Enter` ends the line, whether it is sent as `\r`, `\n` or `\r\n`.
+
+use crate::io::{self, Read, Write};
+use crate::stack_vec::{StackVec, VecLike};
+
+/// Number of lines kept in a `History`.
+pub const HISTORY_LEN: usize = 16;
+/// Maximum length in bytes of a line kept in a `History`. Longer lines aren't
+/// kept.
+pub const HISTORY_LINE_LEN: usize = 128;
+
+const CTRL_C: u8 = 0x03;
+const BELL: u8 = 0x07;
+const BACKSPACE: u8 = 0x08;
+const LF: u8 = b'\n';
+const CR: u8 = b'\r';
+const CTRL_U: u8 = 0x15;
+const ESC: u8 = 0x1b;
+const DELETE: u8 = 0x7f;
+
+/// Erases from the cursor to the end of the line.
+const ERASE_TO_END: &[u8] = b"\x1b[K";
+
+/// A fixed-size ring of the most recent lines.
+pub struct History {
+    entries: [[u8; HISTORY_LINE_LEN]; HISTORY_LEN],
+    lens: [usize; HISTORY_LEN],
+    next: usize,
+    len: usize,
+}
+
+impl Default for History {
+    fn default() -> History {
+        History::new()
+    }
+}
+
+impl History {
+    /// Returns an empty history.
+    pub fn new() -> History {
+        History {
+            entries: [[0; HISTORY_LINE_LEN]; HISTORY_LEN],
+            lens: [0; HISTORY_LEN],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of lines in the history.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the history has no lines.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the `i`th most recent line, starting from 0.
+    pub fn get(&self, i: usize) -> Option<&[u8]> {
+        if i >= self.len {
+            return None;
+        }
+
+        let index = (self.next + HISTORY_LEN - 1 - i) % HISTORY_LEN;
+        Some(&self.entries[index][..self.lens[index]])
+    }
+
+    /// Adds `line` as the most recent line, replacing the oldest one if the
+    /// history is full. Empty lines, lines longer than `HISTORY_LINE_LEN` and
+    /// repeats of the most recent line are ignored.
+    pub fn push(&mut self, line: &[u8]) {
+        if line.is_empty() || line.len() > HISTORY_LINE_LEN || self.get(0) == Some(line) {
+            return;
+        }
+
+        self.entries[self.next][..line.len()].copy_from_slice(line);
+        self.lens[self.next] = line.len();
+        self.next = (self.next + 1) % HISTORY_LEN;
+        if self.len < HISTORY_LEN {
+            self.len += 1;
+        }
+    }
+
+    /// Removes every line.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+/// A key read from the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Byte(u8),
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    Delete,
+    Unknown,
+}
+
+/// Reads lines with editing keys and a command history. See the module
+/// documentation for the supported keys.
+pub struct LineEditor {
+    history: History,
+    draft: [u8; HISTORY_LINE_LEN],  // line being edited while recalling
+    draft_len: usize,
+    skip_lf: bool,                  // last line ended with `\r`
+}
+
+impl Default for LineEditor {
+    fn default() -> LineEditor {
+        LineEditor::new()
+    }
+}
+
+impl LineEditor {
+    /// Returns a line editor with an empty history.
+    pub fn new() -> LineEditor {
+        LineEditor {
+            history: History::new(),
+            draft: [0; HISTORY_LINE_LEN],
+            draft_len: 0,
+            skip_lf: false,
+        }
+    }
+
+    /// Returns the history of the lines read.
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Returns the history of the lines read, e.g. to clear it.
+    pub fn history_mut(&mut self) -> &mut History {
+        &mut self.history
+    }
+
+    /// Reads a line from `stream` into `line`, echoing the edits to `stream`,
+    /// and returns its length. `line` is cleared first and doesn't include
+    /// the line ending. Bytes that don't fit in `line` are rejected with a
+    /// bell. The line is added to the history.
+    ///
+    /// Returns `None` if the line is cancelled with `Ctrl-C`. `line` is
+    /// cleared then.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading from or writing to `stream` fails. An
+    /// error of kind `UnexpectedEof` is returned if the stream ends before
+    /// the line does, and `line` is cleared.
+    pub fn read_line<T: Read + Write>(&mut self, stream: &mut T, line: &mut StackVec<u8>)
+        -> io::Result<Option<usize>>
+    {
+        line.truncate(0);
+        let mut cursor = 0;
+        let mut recalled = None;
+        loop {
+            let key = match read_key(stream) {
+                Err(e) => {
+                    line.truncate(0);
+                    return Err(e);
+                }
+                Ok(key) => key,
+            };
+            let skip_lf = self.skip_lf;
+            self.skip_lf = false;
+            match key {
+                Key::Byte(LF) if skip_lf => continue,
+                Key::Byte(CR) | Key::Byte(LF) => {
+                    self.skip_lf = key == Key::Byte(CR);
+                    stream.write_all(b"\r\n")?;
+                    stream.flush()?;
+                    self.history.push(line);
+                    return Ok(Some(line.len()));
+                }
+                Key::Byte(CTRL_C) => {
+                    stream.write_all(b"^C\r\n")?;
+                    stream.flush()?;
+                    line.truncate(0);
+                    return Ok(None);
+                }
+                Key::Byte(BACKSPACE) | Key::Byte(DELETE) if cursor > 0 => {
+                    cursor -= 1;
+                    line.remove(cursor);
+                    stream.write_byte(BACKSPACE)?;
+                    redraw_tail(stream, line, cursor)?;
+                }
+                Key::Delete if cursor < line.len() => {
+                    line.remove(cursor);
+                    redraw_tail(stream, line, cursor)?;
+                }
+                Key::Byte(CTRL_U) => {
+                    move_left(stream, cursor)?;
+                    stream.write_all(ERASE_TO_END)?;
+                    line.truncate(0);
+                    cursor = 0;
+                }
+                Key::Byte(byte @ 0x20..=0x7e) => {
+                    if line.insert(cursor, byte).is_err() {
+                        stream.write_byte(BELL)?;
+                    } else {
+                        stream.write_all(&line[cursor..])?;
+                        cursor += 1;
+                        move_left(stream, line.len() - cursor)?;
+                    }
+                }
+                Key::Left if cursor > 0 => {
+                    cursor -= 1;
+                    stream.write_byte(BACKSPACE)?;
+                }
+                Key::Right if cursor < line.len() => {
+                    stream.write_byte(line[cursor])?;
+                    cursor += 1;
+                }
+                Key::Home => {
+                    move_left(stream, cursor)?;
+                    cursor = 0;
+                }
+                Key::End => {
+                    stream.write_all(&line[cursor..])?;
+                    cursor = line.len();
+                }
+                Key::Up | Key::Down => {
+                    let index = match (key, recalled) {
+                        (Key::Up, None) if !self.history.is_empty() => Some(0),
+                        (Key::Up, Some(i)) if i + 1 < self.history.len() => Some(i + 1),
+                        (Key::Down, Some(0)) => None,
+                        (Key::Down, Some(i)) => Some(i - 1),
+                        _ => {
+                            stream.write_byte(BELL)?;
+                            stream.flush()?;
+                            continue;
+                        }
+                    };
+                    if recalled.is_none() {
+                        self.draft_len = ::core::cmp::min(line.len(), HISTORY_LINE_LEN);
+                        self.draft[..self.draft_len].copy_from_slice(&line[..self.draft_len]);
+                    }
+                    recalled = index;
+
+                    move_left(stream, cursor)?;
+                    line.truncate(0);
+                    let text = match index {
+                        Some(i) => self.history.get(i).unwrap_or(&[]),
+                        None => &self.draft[..self.draft_len],
+                    };
+                    for byte in text {
+                        if line.push(*byte).is_err() {
+                            break;
+                        }
+                    }
+                    stream.write_all(line)?;
+                    stream.write_all(ERASE_TO_END)?;
+                    cursor = line.len();
+                }
+                _ => stream.write_byte(BELL)?,
+            }
+            stream.flush()?;
+        }
+    }
+}
+
+/// Moves the terminal cursor `n` columns to the left.
+fn move_left<T: Write>(stream: &mut T, n: usize) -> io::Result<()> {
+    for _ in 0..n {
+        stream.write_byte(BACKSPACE)?;
+    }
+    Ok(())
+}
+
+/// Writes `line` from `cursor` on over the end of the terminal line and
+/// moves the terminal cursor back to `cursor`.
+fn redraw_tail<T: Write>(stream: &mut T, line: &[u8], cursor: usize) -> io::Result<()> {
+    stream.write_all(&line[cursor..])?;
+    stream.write_all(ERASE_TO_END)?;
+    move_left(stream, line.len() - cursor)
+}
+
+/// Reads a key: a byte, or the escape sequence of a special key. Unknown
+/// escape sequences are read to their end.
+fn read_key<T: Read>(stream: &mut T) -> io::Result<Key> {
+    let byte = stream.read_byte()?;
+    if byte != ESC {
+        return Ok(Key::Byte(byte));
+    }
+
+    let kind = stream.read_byte()?;
+    if kind != b'[' && kind != b'O' {
+        return Ok(Key::Unknown);
+    }
+
+    // Parameter and intermediate bytes are followed by a final byte.
+    let mut parameter = 0u32;
+    loop {
+        let byte = stream.read_byte()?;
+        match byte {
+            b'0'..=b'9' => parameter = parameter.saturating_mul(10).saturating_add((byte - b'0') as u32),
+            0x20..=0x3f => {}
+            b'A' => return Ok(Key::Up),
+            b'B' => return Ok(Key::Down),
+            b'C' => return Ok(Key::Right),
+            b'D' => return Ok(Key::Left),
+            b'H' => return Ok(Key::Home),
+            b'F' => return Ok(Key::End),
+            b'~' => return Ok(match parameter {
+                1 | 7 => Key::Home,
+                3 => Key::Delete,
+                4 | 8 => Key::End,
+                _ => Key::Unknown,
+            }),
+            _ => return Ok(Key::Unknown),
+        }
+    }
+}
+
+#[cfg(test)]
+mod line_editor_test {
+    use super::*;
+    use crate::io::ErrorKind;
+    use std::vec::Vec;
+
+    /// A terminal sending the bytes of a script and recording the echo.
+    struct Terminal<'a> {
+        input: &'a [u8],
+        output: Vec<u8>,
+    }
+
+    impl<'a> Terminal<'a> {
+        fn new(input: &'a [u8]) -> Terminal<'a> {
+            Terminal { input, output: Vec::new() }
+        }
+    }
+
+    impl<'a> Read for Terminal<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl<'a> Write for Terminal<'a> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Reads the lines of `input` until it ends and returns them.
+    fn read_lines(editor: &mut LineEditor, input: &[u8]) -> Vec<Vec<u8>> {
+        let mut terminal = Terminal::new(input);
+        let mut lines = Vec::new();
+        let mut storage = [0u8; 16];
+        let mut line = StackVec::new(&mut storage);
+        loop {
+            match editor.read_line(&mut terminal, &mut line) {
+                Ok(Some(n)) => {
+                    assert_eq!(n, line.len());
+                    lines.push(line.to_vec());
+                }
+                Ok(None) => panic!("unexpected Ctrl-C"),
+                Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => return lines,
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let lines = read_lines(&mut LineEditor::new(), b"ls\rcat a\r\nb\n\r\n");
+        assert_eq!(lines, vec![b"ls".to_vec(), b"cat a".to_vec(), b"b".to_vec(), vec![]]);
+    }
+
+    #[test]
+    fn test_echo() {
+        let mut terminal = Terminal::new(b"ab\x7fc\r");
+        let mut storage = [0u8; 16];
+        let mut line = StackVec::new(&mut storage);
+        LineEditor::new().read_line(&mut terminal, &mut line).expect("line");
+        assert_eq!(&line[..], b"ac");
+        assert_eq!(&terminal.output[..], &b"ab\x08\x1b[Kc\r\n"[..]);
+    }
+
+    #[test]
+    fn test_editing_keys() {
+        let mut editor = LineEditor::new();
+        // Insert in the middle, delete under the cursor, then go home.
+        let lines = read_lines(&mut editor, b"acd\x1b[D\x1b[Db\x1b[3~\x1b[Hx\x1b[Fy\r");
+        assert_eq!(lines, vec![b"xabdy".to_vec()]);
+
+        let lines = read_lines(&mut editor, b"junk\x15ok\x1bOD\x7f\x7f\r");
+        assert_eq!(lines, vec![b"k".to_vec()]);
+    }
+
+    #[test]
+    fn test_overflow_and_unknown_keys() {
+        let mut terminal = Terminal::new(b"0123456789abcdefXY\x1b[5~\x01\r");
+        let mut storage = [0u8; 16];
+        let mut line = StackVec::new(&mut storage);
+        LineEditor::new().read_line(&mut terminal, &mut line).expect("line");
+        assert_eq!(&line[..], b"0123456789abcdef");
+        assert_eq!(terminal.output.iter().filter(|b| **b == BELL).count(), 4);
+    }
+
+    #[test]
+    fn test_ctrl_c_and_eof() {
+        let mut editor = LineEditor::new();
+        let mut terminal = Terminal::new(b"rm -rf\x03half");
+        let mut storage = [0u8; 16];
+        let mut line = StackVec::new(&mut storage);
+        assert_eq!(editor.read_line(&mut terminal, &mut line).expect("cancelled"), None);
+        assert!(line.is_empty());
+        assert!(terminal.output.ends_with(b"^C\r\n"));
+
+        let e = editor.read_line(&mut terminal, &mut line).expect_err("ended");
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+        assert!(line.is_empty());
+        assert!(editor.history().is_empty());
+    }
+
+    #[test]
+    fn test_history() {
+        let mut editor = LineEditor::new();
+        read_lines(&mut editor, b"one\rtwo\rtwo\r\r");
+        assert_eq!(editor.history().len(), 2);
+        assert_eq!(editor.history().get(0), Some(&b"two"[..]));
+        assert_eq!(editor.history().get(1), Some(&b"one"[..]));
+
+        // Recall "one", edit it, and come back down to the draft.
+        let lines = read_lines(&mut editor, b"\x1b[A\x1b[A\x1b[A!\rdraft\x1b[A\x1b[B\r");
+        assert_eq!(lines, vec![b"one!".to_vec(), b"draft".to_vec()]);
+        assert_eq!(editor.history().get(0), Some(&b"draft"[..]));
+        assert_eq!(editor.history().len(), 4);
+    }
+
+    #[test]
+    fn test_history_ring() {
+        let mut history = History::new();
+        for i in 0..HISTORY_LEN + 2 {
+            history.push(&[b'a' + i as u8]);
+        }
+        assert_eq!(history.len(), HISTORY_LEN);
+        assert_eq!(history.get(0), Some(&[b'a' + HISTORY_LEN as u8 + 1][..]));
+        assert_eq!(history.get(HISTORY_LEN - 1), Some(&b"c"[..]));
+        assert_eq!(history.get(HISTORY_LEN), None);
+
+        history.push(&[b'x'; HISTORY_LINE_LEN + 1]);
+        assert_eq!(history.get(0), Some(&[b'a' + HISTORY_LEN as u8 + 1][..]));
+    }
+}
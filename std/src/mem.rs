@@ -1,7 +1,7 @@
 /// The module to write byte into memory.
 use crate::io::*;
 use core::result::Result;
-use core::result::Result::{Ok, Err};
+use core::result::Result::Ok;
 
 pub struct MemWrite{
     i: usize, // the index now
@@ -12,21 +12,26 @@ impl MemWrite{
     pub fn new(start:usize, end:usize)->MemWrite{
         MemWrite{
             i: start,
-            end: end,
+            end,
         }
     }
 }
 
 impl Write for MemWrite{
-    fn write_byte(&mut self, byte: u8) -> Result<u8, ErrorKind>{
-        if self.i==self.end {
-            return Err(ErrorKind::UnexpectedEof);
+    /// Writes as many bytes of `buf` as fit before the end address.
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error>{
+        let n = ::core::cmp::min(buf.len(), self.end - self.i);
+        for byte in &buf[..n] {
+            unsafe {
+                let address: *mut u8 = self.i as *mut u8;
+                ::core::ptr::write_volatile(address, *byte);
+            }
+            self.i += 1;
         }
-        unsafe {
-            let address: *mut u8 = self.i as *mut u8;
-            ::core::ptr::write_volatile(address, byte);
-        }
-        self.i += 1;
-        Ok(byte)
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<(), Error>{
+        Ok(())
     }
 }
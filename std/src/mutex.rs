@@ -1,12 +1,37 @@
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use core::cell::UnsafeCell;
+use core::hint;
+use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
 use core::ops::{DerefMut, Deref, Drop};
 
+use crate::irq::{Daif, Interrupts};
+use crate::sync::exclusive;
+
+#[cfg(debug_assertions)]
+mod diagnostics;
+
+/// A spinlock protecting a `T`.
+///
+/// The lock is taken with an atomic compare-and-swap, so it is safe to share
+/// between cores. It isn't fair: see `TicketMutex` for a lock that is taken
+/// in the order it was asked for.
+///
+/// In debug builds, the mutex records which context holds it and where it was
+/// locked, and panics on recursive locking and on probable deadlocks. See
+/// `set_owner_id`.
+///
+/// On the Raspberry Pi, the exclusive loads and stores behind atomic
+/// read-modify-write operations only succeed on cacheable memory, i.e. once
+/// the MMU and data cache are enabled. Until `sync::exclusive::enable` is
+/// called, the lock is taken with a plain load and store instead, which is
+/// only sound on a single core with IRQs masked.
 #[repr(align(32))]
 pub struct Mutex<T> {
     data: UnsafeCell<T>,
-    #[allow(unused)]
     lock: AtomicBool,
+    #[cfg(debug_assertions)]
+    owner: diagnostics::Owner,
 }
 
 unsafe impl<T: Send> Send for Mutex<T> { }
@@ -23,25 +48,80 @@ impl<T> Mutex<T> {
     pub const fn new(val: T) -> Mutex<T> {
         Mutex {
             lock: AtomicBool::new(false),
-            data: UnsafeCell::new(val)
+            data: UnsafeCell::new(val),
+            #[cfg(debug_assertions)]
+            owner: diagnostics::Owner::new(),
         }
     }
+
+    /// Consumes the mutex and returns the protected value.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    /// Returns a mutable reference to the protected value. No locking is
+    /// needed since `self` is borrowed mutably.
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
 }
 
 impl<T> Mutex<T> {
-    // Once MMU/cache is enabled, do the right thing here. For now, we don't
-    // need any real synchronization.
+    /// Acquires the lock, spinning until it is available.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, panics if the current context holds the lock
+    /// already, or if it spins for `DEADLOCK_SPINS` iterations.
     #[inline(never)]
-    pub fn lock(&self) -> MutexGuard<T> {
-        // Wait until we can "aquire" the lock, then "acquire" it.
-        while self.lock.load(Ordering::Relaxed) { }
-        self.lock.store(true, Ordering::Relaxed);
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        #[cfg(debug_assertions)]
+        self.owner.check_recursion();
+        #[cfg(debug_assertions)]
+        let mut spins = 0;
+
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
 
-        MutexGuard { lock: &self }
+            // Wait with plain loads until the lock looks free so that waiting
+            // cores don't fight over the cache line.
+            while self.lock.load(Ordering::Relaxed) {
+                #[cfg(debug_assertions)]
+                {
+                    spins += 1;
+                    if spins == diagnostics::DEADLOCK_SPINS {
+                        self.owner.report_deadlock();
+                    }
+                }
+                hint::spin_loop();
+            }
+        }
+    }
+
+    /// Acquires the lock if it is available, or returns `None` without
+    /// blocking.
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        exclusive::compare_exchange(&self.lock, false, true, Ordering::Acquire, Ordering::Relaxed).ok()?;
+        #[cfg(debug_assertions)]
+        self.owner.acquired();
+        Some(MutexGuard { lock: self })
     }
 
     fn unlock(&self) {
-        self.lock.store(false, Ordering::Relaxed);
+        #[cfg(debug_assertions)]
+        self.owner.released();
+        self.lock.store(false, Ordering::Release);
+    }
+}
+
+impl<'a, T: 'a> MutexGuard<'a, T> {
+    /// Returns the mutex that `guard` locks.
+    pub(crate) fn mutex(guard: &MutexGuard<'a, T>) -> &'a Mutex<T> {
+        guard.lock
     }
 }
 
@@ -64,3 +144,395 @@ impl<'a, T: 'a> Drop for MutexGuard<'a, T> {
         self.lock.unlock()
     }
 }
+
+/// A `Mutex` that masks the current core's interrupts while it is held.
+///
+/// Data shared with an interrupt handler must be protected by an `IrqMutex`:
+/// if the handler interrupted a holder of a plain `Mutex` on the same core
+/// and tried to take it, it would spin forever. `lock` saves the interrupt
+/// mask state through `I` and masks IRQs and FIQs before taking the lock;
+/// the guard releases the lock and then restores the saved state, so nested
+/// locks keep interrupts masked until the outermost guard is dropped.
+pub struct IrqMutex<T, I = Daif> {
+    mutex: Mutex<T>,
+    _interrupts: PhantomData<I>,
+}
+
+unsafe impl<T: Send, I> Send for IrqMutex<T, I> { }
+unsafe impl<T: Send, I> Sync for IrqMutex<T, I> { }
+
+pub struct IrqMutexGuard<'a, T: 'a, I: Interrupts> {
+    guard: ManuallyDrop<MutexGuard<'a, T>>,
+    state: I::State,
+}
+
+impl<T, I> IrqMutex<T, I> {
+    pub const fn new(val: T) -> IrqMutex<T, I> {
+        IrqMutex {
+            mutex: Mutex::new(val),
+            _interrupts: PhantomData,
+        }
+    }
+
+    /// Consumes the mutex and returns the protected value.
+    pub fn into_inner(self) -> T {
+        self.mutex.into_inner()
+    }
+
+    /// Returns a mutable reference to the protected value. No locking is
+    /// needed since `self` is borrowed mutably.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.mutex.get_mut()
+    }
+}
+
+impl<T, I: Interrupts> IrqMutex<T, I> {
+    /// Masks interrupts and acquires the lock, spinning until it is
+    /// available. Panics like `Mutex::lock` in debug builds.
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn lock(&self) -> IrqMutexGuard<'_, T, I> {
+        let state = I::save_and_mask();
+        IrqMutexGuard { guard: ManuallyDrop::new(self.mutex.lock()), state }
+    }
+
+    /// Masks interrupts and acquires the lock if it is available. Otherwise
+    /// restores the interrupt mask state and returns `None`.
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn try_lock(&self) -> Option<IrqMutexGuard<'_, T, I>> {
+        let state = I::save_and_mask();
+        match self.mutex.try_lock() {
+            Some(guard) => Some(IrqMutexGuard { guard: ManuallyDrop::new(guard), state }),
+            None => {
+                I::restore(state);
+                None
+            }
+        }
+    }
+}
+
+impl<'a, T: 'a, I: Interrupts> Deref for IrqMutexGuard<'a, T, I> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'a, T: 'a, I: Interrupts> DerefMut for IrqMutexGuard<'a, T, I> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<'a, T: 'a, I: Interrupts> Drop for IrqMutexGuard<'a, T, I> {
+    fn drop(&mut self) {
+        // Unlock first: an interrupt taken as soon as the state is restored
+        // may want the lock.
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        I::restore(self.state);
+    }
+}
+
+/// Installs `current` as the function returning the identifier of the
+/// current context, e.g. the running task's, which debug builds of `Mutex`
+/// record as the holder. By default, the identifier is the number of the
+/// core, or the current thread's on the host.
+///
+/// # Errors
+///
+/// Returns `current` back if a function is already installed; it can only be
+/// installed once. Release builds don't record holders and always succeed.
+pub fn set_owner_id(current: fn() -> usize) -> Result<(), fn() -> usize> {
+    #[cfg(debug_assertions)]
+    return diagnostics::set_owner_id(current);
+
+    #[cfg(not(debug_assertions))]
+    {
+        let _ = current;
+        Ok(())
+    }
+}
+
+/// A fair spinlock protecting a `T`.
+///
+/// Each caller of `lock` draws a ticket and waits until it is served, so the
+/// lock is acquired in the order it was asked for and no waiter starves. The
+/// same caveat as `Mutex` applies on the Raspberry Pi.
+#[repr(align(32))]
+pub struct TicketMutex<T> {
+    data: UnsafeCell<T>,
+    next_ticket: AtomicUsize,
+    now_serving: AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for TicketMutex<T> { }
+unsafe impl<T: Send> Sync for TicketMutex<T> { }
+
+pub struct TicketMutexGuard<'a, T: 'a> {
+    lock: &'a TicketMutex<T>
+}
+
+impl<'a, T> !Send for TicketMutexGuard<'a, T> { }
+unsafe impl<'a, T: Sync> Sync for TicketMutexGuard<'a, T> { }
+
+impl<T> TicketMutex<T> {
+    pub const fn new(val: T) -> TicketMutex<T> {
+        TicketMutex {
+            data: UnsafeCell::new(val),
+            next_ticket: AtomicUsize::new(0),
+            now_serving: AtomicUsize::new(0),
+        }
+    }
+
+    /// Consumes the mutex and returns the protected value.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    /// Returns a mutable reference to the protected value. No locking is
+    /// needed since `self` is borrowed mutably.
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
+
+    /// Acquires the lock, spinning until every earlier caller has released
+    /// it.
+    #[inline(never)]
+    pub fn lock(&self) -> TicketMutexGuard<'_, T> {
+        let ticket = exclusive::fetch_add(&self.next_ticket, 1, Ordering::Relaxed);
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            hint::spin_loop();
+        }
+
+        TicketMutexGuard { lock: self }
+    }
+
+    /// Acquires the lock if nobody holds or waits for it, or returns `None`
+    /// without blocking.
+    pub fn try_lock(&self) -> Option<TicketMutexGuard<'_, T>> {
+        let ticket = self.now_serving.load(Ordering::Relaxed);
+        exclusive::compare_exchange(&self.next_ticket, ticket, ticket.wrapping_add(1), Ordering::Acquire,
+                                    Ordering::Relaxed)
+            .ok()
+            .map(|_| TicketMutexGuard { lock: self })
+    }
+
+    fn unlock(&self) {
+        // Only the holder writes `now_serving`.
+        let ticket = self.now_serving.load(Ordering::Relaxed);
+        self.now_serving.store(ticket.wrapping_add(1), Ordering::Release);
+    }
+}
+
+impl<'a, T: 'a> Deref for TicketMutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { & *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: 'a> DerefMut for TicketMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: 'a> Drop for TicketMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.unlock()
+    }
+}
+
+#[cfg(test)]
+mod mutex_test {
+    use super::*;
+    use core::cell::Cell;
+    use core::ptr;
+    use std::thread;
+    #[cfg(debug_assertions)]
+    use std::{any::Any, boxed::Box, format, panic::{self, AssertUnwindSafe}, string::String};
+
+    const THREADS: usize = 4;
+    const ITERATIONS: usize = 20_000;
+
+    /// Increments `counter` in a way that loses updates unless the caller
+    /// has exclusive access.
+    fn increment(counter: &mut usize) {
+        unsafe {
+            let value = ptr::read_volatile(counter);
+            ptr::write_volatile(counter, value + 1);
+        }
+    }
+
+    #[test]
+    fn test_try_lock() {
+        let mutex = Mutex::new(1);
+        {
+            let mut guard = mutex.try_lock().expect("unlocked");
+            assert!(mutex.try_lock().is_none());
+            *guard += 1;
+        }
+        assert_eq!(*mutex.lock(), 2);
+        assert!(mutex.try_lock().is_some());
+        assert_eq!(mutex.into_inner(), 2);
+    }
+
+    /// Returns the message of a panic caught by `catch_unwind` or `join`.
+    #[cfg(debug_assertions)]
+    fn panic_message(payload: Box<dyn Any + Send>) -> String {
+        match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(_) => String::new(),
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn test_recursive_lock_panics() {
+        let mutex = Mutex::new(0);
+        let _guard = mutex.lock();
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| *mutex.lock())).expect_err("recursion detected");
+        let message = panic_message(payload);
+        assert!(message.starts_with("Mutex locked recursively"), "{}", message);
+        assert_eq!(message.matches(file!()).count(), 2, "{}", message);
+        // `try_lock` by the holder just fails.
+        assert!(mutex.try_lock().is_none());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn test_deadlock_reported() {
+        let mutex = Mutex::new(0);
+        let guard = mutex.lock();
+        let line = line!() - 1;
+        let owner = diagnostics::current_id();
+        let payload = thread::scope(|scope| {
+            scope.spawn(|| *mutex.lock()).join().expect_err("deadlock reported")
+        });
+        let message = panic_message(payload);
+        assert!(message.starts_with("probable deadlock"), "{}", message);
+        assert!(message.contains(&format!("held by owner {:#x} since {}:{}:", owner, file!(), line)), "{}", message);
+        drop(guard);
+        assert_eq!(*mutex.lock(), 0);
+    }
+
+    #[test]
+    fn test_mutual_exclusion() {
+        let mutex = Mutex::new(0);
+        thread::scope(|scope| {
+            for _ in 0..THREADS {
+                scope.spawn(|| {
+                    for i in 0..ITERATIONS {
+                        match i % 2 {
+                            0 => increment(&mut mutex.lock()),
+                            _ => loop {
+                                if let Some(mut guard) = mutex.try_lock() {
+                                    break increment(&mut guard);
+                                }
+                                thread::yield_now();
+                            },
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(*mutex.lock(), THREADS * ITERATIONS);
+    }
+
+    thread_local! {
+        static MASKED: Cell<bool> = const { Cell::new(false) };
+    }
+
+    /// Interrupts of the current test thread.
+    struct FakeInterrupts;
+
+    impl FakeInterrupts {
+        fn masked() -> bool {
+            MASKED.with(|masked| masked.get())
+        }
+    }
+
+    impl Interrupts for FakeInterrupts {
+        type State = bool;
+
+        fn save_and_mask() -> bool {
+            MASKED.with(|masked| masked.replace(true))
+        }
+
+        fn restore(state: bool) {
+            MASKED.with(|masked| masked.set(state));
+        }
+    }
+
+    #[test]
+    fn test_irq_mutex_masks() {
+        let outer: IrqMutex<u8, FakeInterrupts> = IrqMutex::new(1);
+        let inner: IrqMutex<u8, FakeInterrupts> = IrqMutex::new(2);
+        assert!(!FakeInterrupts::masked());
+        {
+            let mut guard = outer.lock();
+            assert!(FakeInterrupts::masked());
+            *guard += 1;
+            {
+                let inner = inner.try_lock().expect("unlocked");
+                assert_eq!(*inner, 2);
+            }
+            // Still masked by the outer guard.
+            assert!(FakeInterrupts::masked());
+
+            assert!(outer.try_lock().is_none());
+            assert!(FakeInterrupts::masked());
+        }
+        assert!(!FakeInterrupts::masked());
+        assert!(outer.try_lock().is_some());
+        assert!(!FakeInterrupts::masked());
+        assert_eq!(outer.into_inner(), 2);
+    }
+
+    #[test]
+    fn test_irq_mutex_mutual_exclusion() {
+        let mutex: IrqMutex<usize, FakeInterrupts> = IrqMutex::new(0);
+        thread::scope(|scope| {
+            for _ in 0..THREADS {
+                scope.spawn(|| {
+                    for _ in 0..ITERATIONS {
+                        increment(&mut mutex.lock());
+                    }
+                    assert!(!FakeInterrupts::masked());
+                });
+            }
+        });
+        assert_eq!(*mutex.lock(), THREADS * ITERATIONS);
+    }
+
+    #[test]
+    fn test_ticket_try_lock() {
+        let mut mutex = TicketMutex::new(1);
+        {
+            let mut guard = mutex.lock();
+            assert!(mutex.try_lock().is_none());
+            *guard += 1;
+        }
+        *mutex.try_lock().expect("unlocked") += 1;
+        *mutex.get_mut() += 1;
+        assert_eq!(*mutex.lock(), 4);
+    }
+
+    #[test]
+    fn test_ticket_mutual_exclusion() {
+        let mutex = TicketMutex::new(0);
+        thread::scope(|scope| {
+            for _ in 0..THREADS {
+                scope.spawn(|| {
+                    // Waiters spin while the next ticket holder may be
+                    // descheduled, so keep this short on few-core hosts.
+                    for _ in 0..ITERATIONS / 40 {
+                        increment(&mut mutex.lock());
+                    }
+                });
+            }
+        });
+        assert_eq!(mutex.into_inner(), THREADS * ITERATIONS / 40);
+    }
+}
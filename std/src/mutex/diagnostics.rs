@@ -0,0 +1,109 @@
+//! Lock diagnostics of debug builds.
+//!
+//! A debug build's `Mutex` records which context holds it and where it was
+//! locked. Locking it again from the same context panics instead of spinning
+//! forever, and so does spinning on it for `DEADLOCK_SPINS` iterations, with
+//! a message naming the holder.
+
+use core::panic::Location;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use crate::sync::OnceCell;
+
+/// The number of iterations after which spinning on a `Mutex` is reported as
+/// a probable deadlock. Tests use a lower value to report quickly.
+#[cfg(not(test))]
+pub const DEADLOCK_SPINS: usize = 1 << 28;
+#[cfg(test)]
+pub const DEADLOCK_SPINS: usize = 1 << 22;
+
+/// The `id` of a `Mutex` that isn't held.
+const NONE: usize = usize::MAX;
+
+static OWNER_ID: OnceCell<fn() -> usize> = OnceCell::new();
+
+/// Installs the function that identifies the current context. See
+/// `mutex::set_owner_id`.
+pub fn set_owner_id(current: fn() -> usize) -> Result<(), fn() -> usize> {
+    OWNER_ID.set(current)
+}
+
+/// Returns the identifier of the current context: the installed function's,
+/// or else the current thread's on the host and the core's number on the Pi.
+pub fn current_id() -> usize {
+    if let Some(current) = OWNER_ID.get() {
+        return current();
+    }
+
+    #[cfg(any(test, feature = "host"))]
+    {
+        host::thread_local! {
+            static THREAD: u8 = const { 0 };
+        }
+        THREAD.with(|thread| thread as *const u8 as usize)
+    }
+
+    #[cfg(all(not(any(test, feature = "host")), target_arch = "aarch64"))]
+    unsafe {
+        let mpidr: usize;
+        core::arch::asm!("mrs {}, MPIDR_EL1", out(reg) mpidr, options(nomem, nostack));
+        mpidr & 0xff
+    }
+
+    #[cfg(all(not(any(test, feature = "host")), not(target_arch = "aarch64")))]
+    0
+}
+
+/// The holder of a `Mutex` and where it locked it.
+pub struct Owner {
+    id: AtomicUsize,
+    location: AtomicPtr<Location<'static>>,
+}
+
+impl Owner {
+    pub const fn new() -> Owner {
+        Owner { id: AtomicUsize::new(NONE), location: AtomicPtr::new(ptr::null_mut()) }
+    }
+
+    /// Panics if the current context holds the mutex.
+    #[track_caller]
+    pub fn check_recursion(&self) {
+        let id = current_id();
+        if self.id.load(Ordering::Acquire) == id {
+            panic!("Mutex locked recursively by owner {:#x} at {}; it was locked at {}",
+                   id, Location::caller(), self.location());
+        }
+    }
+
+    /// Records the current context as the holder, locking at the caller's
+    /// location.
+    #[track_caller]
+    pub fn acquired(&self) {
+        let location: &'static Location<'static> = Location::caller();
+        self.location.store(location as *const _ as *mut _, Ordering::Relaxed);
+        self.id.store(current_id(), Ordering::Release);
+    }
+
+    /// Forgets the holder, before the mutex is unlocked.
+    pub fn released(&self) {
+        self.id.store(NONE, Ordering::Relaxed);
+    }
+
+    /// Panics with a report of a probable deadlock, naming the holder.
+    #[track_caller]
+    pub fn report_deadlock(&self) -> ! {
+        match self.id.load(Ordering::Acquire) {
+            NONE => panic!("probable deadlock: Mutex spun on {} times at {}",
+                           DEADLOCK_SPINS, Location::caller()),
+            id => panic!("probable deadlock: Mutex spun on {} times at {}; held by owner {:#x} since {}",
+                         DEADLOCK_SPINS, Location::caller(), id, self.location()),
+        }
+    }
+
+    /// Returns where the holder locked the mutex. Only valid once `id` was
+    /// seen set: `location` is stored before it, and never cleared.
+    fn location(&self) -> &'static Location<'static> {
+        unsafe { &*self.location.load(Ordering::Relaxed) }
+    }
+}
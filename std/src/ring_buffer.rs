@@ -0,0 +1,214 @@
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::ptr;
+
+/// A first-in first-out queue holding up to `N` elements inline.
+///
+/// `RingBuffer` requires no memory allocation. Elements pushed while it is
+/// full are either rejected with `push` or replace the oldest element with
+/// `push_overwrite`; both are counted as overflows. See `spsc::Queue` to
+/// share a queue between an interrupt handler and thread context.
+pub struct RingBuffer<T, const N: usize> {
+    storage: [MaybeUninit<T>; N],
+    head: usize,        // index of the oldest element
+    len: usize,
+    overflows: usize,   // pushes that found the buffer full
+}
+
+impl<T, const N: usize> RingBuffer<T, N> {
+    /// Returns an empty ring buffer.
+    pub const fn new() -> RingBuffer<T, N> {
+        RingBuffer {
+            // An array of `MaybeUninit` needs no initialization.
+            storage: unsafe { MaybeUninit::<[MaybeUninit<T>; N]>::uninit().assume_init() },
+            head: 0,
+            len: 0,
+            overflows: 0,
+        }
+    }
+
+    /// Returns the number of elements the buffer can hold, `N`.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of elements in the buffer.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the buffer contains no elements.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if the buffer is at capacity.
+    pub const fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Returns the number of pushes that found the buffer full since it was
+    /// created or `reset_overflows` was called.
+    pub const fn overflows(&self) -> usize {
+        self.overflows
+    }
+
+    /// Resets the overflow count to 0 and returns its previous value.
+    pub fn reset_overflows(&mut self) -> usize {
+        let overflows = self.overflows;
+        self.overflows = 0;
+        overflows
+    }
+
+    /// Appends `value` to the back of the buffer.
+    ///
+    /// # Errors
+    ///
+    /// If the buffer is full, `value` is returned back and the overflow is
+    /// counted.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            self.overflows += 1;
+            return Err(value);
+        }
+
+        self.storage[(self.head + self.len) % N] = MaybeUninit::new(value);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Appends `value` to the back of the buffer. If the buffer is full, the
+    /// oldest element is removed and returned to make room, and the overflow
+    /// is counted.
+    pub fn push_overwrite(&mut self, value: T) -> Option<T> {
+        let evicted = if self.is_full() {
+            self.overflows += 1;
+            self.pop()
+        } else {
+            None
+        };
+
+        let _ = self.push(value);
+        evicted
+    }
+
+    /// Removes the oldest element and returns it, or `None` if the buffer is
+    /// empty.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let value = unsafe { ptr::read(self.storage[self.head].as_ptr()) };
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Returns a reference to the oldest element, or `None` if the buffer is
+    /// empty.
+    pub fn peek(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    /// Returns a reference to the `i`th oldest element, starting from 0.
+    pub fn get(&self, i: usize) -> Option<&T> {
+        if i >= self.len {
+            return None;
+        }
+
+        Some(unsafe { &*self.storage[(self.head + i) % N].as_ptr() })
+    }
+
+    /// Returns an iterator over the elements, from the oldest to the newest.
+    pub fn iter(&self) -> Iter<'_, T, N> {
+        Iter { buffer: self, i: 0 }
+    }
+
+    /// Removes and drops all the elements.
+    pub fn clear(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+impl<T, const N: usize> Drop for RingBuffer<T, N> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> RingBuffer<T, N> {
+        RingBuffer::new()
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for RingBuffer<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Iterator over the elements of a `RingBuffer`, from the oldest to the
+/// newest.
+pub struct Iter<'a, T, const N: usize> {
+    buffer: &'a RingBuffer<T, N>,
+    i: usize,
+}
+
+impl<'a, T, const N: usize> Iterator for Iter<'a, T, N> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let value = self.buffer.get(self.i)?;
+        self.i += 1;
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod ring_buffer_test {
+    use super::*;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_fifo() {
+        let mut buffer: RingBuffer<u8, 3> = RingBuffer::new();
+        assert_eq!(buffer.pop(), None);
+        for round in 0..4 {
+            buffer.push(round).expect("room");
+            buffer.push(round + 1).expect("room");
+            assert_eq!(buffer.pop(), Some(round));
+            assert_eq!(buffer.peek(), Some(&(round + 1)));
+            assert_eq!(buffer.pop(), Some(round + 1));
+        }
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut buffer: RingBuffer<u8, 2> = RingBuffer::new();
+        buffer.push(1).expect("room");
+        buffer.push(2).expect("room");
+        assert_eq!(buffer.push(3), Err(3));
+        assert_eq!(buffer.push_overwrite(4), Some(1));
+        assert_eq!(buffer.iter().cloned().collect::<Vec<_>>(), vec![2, 4]);
+        assert_eq!(buffer.overflows(), 2);
+        assert_eq!(buffer.reset_overflows(), 2);
+        assert_eq!(buffer.overflows(), 0);
+    }
+
+    #[test]
+    fn test_drop() {
+        let rc = Rc::new(());
+        {
+            let mut buffer: RingBuffer<Rc<()>, 3> = RingBuffer::new();
+            for _ in 0..5 {
+                buffer.push_overwrite(rc.clone());
+            }
+            assert_eq!(Rc::strong_count(&rc), 4);
+        }
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+}
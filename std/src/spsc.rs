@@ -0,0 +1,295 @@
+//! A lock-free single-producer single-consumer queue.
+//!
+//! The queue moves elements from one context to another without allocation
+//! or locking, e.g. bytes received by a UART interrupt handler to thread
+//! context. It is split into a `Producer` and a `Consumer`, each of which may
+//! be used from a different context.
+//!
+//! ```rust
+//! # use swift_std::spsc::Queue;
+//! static mut RX: Queue<u8, 64> = Queue::new();
+//!
+//! let (mut producer, mut consumer) = unsafe { RX.split() };
+//! producer.try_push(b'x');
+//! assert_eq!(consumer.try_pop(), Some(b'x'));
+//! ```
+
+use core::cell::UnsafeCell;
+use core::hint;
+use core::mem::MaybeUninit;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A queue holding up to `N` elements, shared by a `Producer` and a
+/// `Consumer`.
+///
+/// The head and tail positions count up to `2 * N` so that a full queue can
+/// be told from an empty one; the slot of a position is the position modulo
+/// `N`.
+///
+/// `N` must not be zero; constructing a `Queue<T, 0>` fails to compile:
+///
+/// ```rust,compile_fail
+/// # use swift_std::spsc::Queue;
+/// let queue: Queue<u8, 0> = Queue::new();
+/// ```
+pub struct Queue<T, const N: usize> {
+    storage: UnsafeCell<[MaybeUninit<T>; N]>,
+    head: AtomicUsize,      // position of the oldest element, written by the consumer
+    tail: AtomicUsize,      // position of the next element, written by the producer
+    overflows: AtomicUsize, // pushes that found the queue full
+}
+
+unsafe impl<T: Send, const N: usize> Sync for Queue<T, N> {}
+
+impl<T, const N: usize> Queue<T, N> {
+    /// Fails to evaluate, and so to compile, if `N` is zero.
+    const NONZERO: () = assert!(N > 0, "a queue must hold at least one element");
+
+    /// Returns an empty queue.
+    pub const fn new() -> Queue<T, N> {
+        let () = Self::NONZERO;
+        Queue {
+            // An array of `MaybeUninit` needs no initialization.
+            storage: UnsafeCell::new(unsafe { MaybeUninit::<[MaybeUninit<T>; N]>::uninit().assume_init() }),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            overflows: AtomicUsize::new(0),
+        }
+    }
+
+    /// Splits the queue into its producer and consumer ends.
+    pub fn split(&mut self) -> (Producer<'_, T, N>, Consumer<'_, T, N>) {
+        (Producer { queue: self }, Consumer { queue: self })
+    }
+
+    /// Returns the number of elements the queue can hold, `N`.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of elements in the queue. It may change as soon as
+    /// it is returned if the other end is in use.
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        (tail + 2 * N - head) % (2 * N)
+    }
+
+    /// Returns true if the queue contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of pushes that found the queue full.
+    pub fn overflows(&self) -> usize {
+        self.overflows.load(Ordering::Relaxed)
+    }
+
+    /// Returns the pointer to the slot of `position`.
+    fn slot(&self, position: usize) -> *mut MaybeUninit<T> {
+        unsafe { (*self.storage.get()).as_mut_ptr().add(position % N) }
+    }
+}
+
+impl<T, const N: usize> Default for Queue<T, N> {
+    fn default() -> Queue<T, N> {
+        Queue::new()
+    }
+}
+
+impl<T, const N: usize> Drop for Queue<T, N> {
+    fn drop(&mut self) {
+        let mut consumer = Consumer { queue: self };
+        while consumer.try_pop().is_some() {}
+    }
+}
+
+/// The pushing end of a `Queue`.
+pub struct Producer<'a, T, const N: usize> {
+    queue: &'a Queue<T, N>,
+}
+
+unsafe impl<'a, T: Send, const N: usize> Send for Producer<'a, T, N> {}
+
+impl<'a, T, const N: usize> Producer<'a, T, N> {
+    /// Appends `value` to the back of the queue without blocking.
+    ///
+    /// # Errors
+    ///
+    /// If the queue is full, `value` is returned back and the overflow is
+    /// counted.
+    pub fn try_push(&mut self, value: T) -> Result<(), T> {
+        let result = self.push_if_room(value);
+        if result.is_err() {
+            self.queue.overflows.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Appends `value` to the back of the queue, spinning until there is
+    /// room. Waiting isn't counted as an overflow.
+    pub fn push(&mut self, mut value: T) {
+        while let Err(rejected) = self.push_if_room(value) {
+            value = rejected;
+            hint::spin_loop();
+        }
+    }
+
+    /// Appends `value` to the back of the queue if it isn't full, or
+    /// returns it back.
+    fn push_if_room(&mut self, value: T) -> Result<(), T> {
+        let tail = self.queue.tail.load(Ordering::Relaxed);
+        let head = self.queue.head.load(Ordering::Acquire);
+        if (tail + 2 * N - head) % (2 * N) == N {
+            return Err(value);
+        }
+
+        unsafe { ptr::write(self.queue.slot(tail), MaybeUninit::new(value)) };
+        self.queue.tail.store((tail + 1) % (2 * N), Ordering::Release);
+        Ok(())
+    }
+
+    /// Returns true if the queue is at capacity.
+    pub fn is_full(&self) -> bool {
+        self.queue.len() == N
+    }
+
+    /// Returns the number of pushes that found the queue full.
+    pub fn overflows(&self) -> usize {
+        self.queue.overflows()
+    }
+}
+
+/// The popping end of a `Queue`.
+pub struct Consumer<'a, T, const N: usize> {
+    queue: &'a Queue<T, N>,
+}
+
+unsafe impl<'a, T: Send, const N: usize> Send for Consumer<'a, T, N> {}
+
+impl<'a, T, const N: usize> Consumer<'a, T, N> {
+    /// Removes the oldest element and returns it, or `None` if the queue is
+    /// empty. Doesn't block.
+    pub fn try_pop(&mut self) -> Option<T> {
+        let head = self.queue.head.load(Ordering::Relaxed);
+        let tail = self.queue.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+
+        let value = unsafe { ptr::read(self.queue.slot(head)).assume_init() };
+        self.queue.head.store((head + 1) % (2 * N), Ordering::Release);
+        Some(value)
+    }
+
+    /// Removes the oldest element and returns it, spinning until there is
+    /// one.
+    pub fn pop(&mut self) -> T {
+        loop {
+            match self.try_pop() {
+                Some(value) => return value,
+                None => hint::spin_loop(),
+            }
+        }
+    }
+
+    /// Returns the number of elements in the queue.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns true if the queue contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Returns the number of pushes that found the queue full.
+    pub fn overflows(&self) -> usize {
+        self.queue.overflows()
+    }
+}
+
+#[cfg(test)]
+mod spsc_test {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_push_pop() {
+        let mut queue: Queue<u8, 3> = Queue::new();
+        let (mut producer, mut consumer) = queue.split();
+        assert_eq!(consumer.try_pop(), None);
+        for round in 0..5 {
+            producer.try_push(round).expect("room");
+            producer.try_push(round + 1).expect("room");
+            producer.try_push(round + 2).expect("room");
+            assert!(producer.is_full());
+            assert_eq!(producer.try_push(0), Err(0));
+            assert_eq!(consumer.len(), 3);
+            assert_eq!(consumer.try_pop(), Some(round));
+            assert_eq!(consumer.try_pop(), Some(round + 1));
+            assert_eq!(consumer.pop(), round + 2);
+        }
+        assert!(consumer.is_empty());
+        assert_eq!(consumer.overflows(), 5);
+    }
+
+    #[test]
+    fn test_drop() {
+        let rc = Arc::new(());
+        {
+            let mut queue: Queue<Arc<()>, 4> = Queue::new();
+            let (mut producer, mut consumer) = queue.split();
+            for _ in 0..3 {
+                producer.push(rc.clone());
+            }
+            consumer.pop();
+            assert_eq!(Arc::strong_count(&rc), 3);
+        }
+        assert_eq!(Arc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn test_two_threads() {
+        const COUNT: u32 = 20_000;
+        let mut queue: Queue<u32, 16> = Queue::new();
+        let (mut producer, mut consumer) = queue.split();
+        let rejected = thread::scope(|scope| {
+            let producing = scope.spawn(move || {
+                let mut rejected = 0;
+                for i in 0..COUNT {
+                    if i % 2 == 0 {
+                        producer.push(i);
+                        continue;
+                    }
+                    let mut value = i;
+                    while let Err(back) = producer.try_push(value) {
+                        rejected += 1;
+                        value = back;
+                        thread::yield_now();
+                    }
+                }
+                rejected
+            });
+            for i in 0..COUNT {
+                let value = if i % 3 == 0 {
+                    consumer.pop()
+                } else {
+                    loop {
+                        if let Some(value) = consumer.try_pop() {
+                            break value;
+                        }
+                        thread::yield_now();
+                    }
+                };
+                assert_eq!(value, i);
+            }
+            assert_eq!(consumer.try_pop(), None);
+            producing.join().expect("producer finished")
+        });
+        assert_eq!(queue.overflows(), rejected);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,232 @@
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::str;
+
+use crate::string_like;
+pub use crate::string_like::{Error, StringLike};
+
+/// A UTF-8 string backed by a slice.
+///
+/// `StackString` is to `String` what `StackVec` is to `Vec`: it requires no
+/// memory allocation as it is backed by a user-supplied slice of bytes, so
+/// its capacity is _bounded_ by the slice. Appending past the capacity fails
+/// with an `Error` rather than panicking, and so does `write!`. The rest of
+/// the `String` API is provided by the `StringLike` trait.
+pub struct StackString<'a> {
+    storage: &'a mut [u8],
+    len: usize
+}
+
+impl<'a> StackString<'a> {
+    /// Constructs a new, empty `StackString` using `storage` as the backing
+    /// store. The returned `StackString` will be able to hold
+    /// `storage.len()` bytes.
+    pub fn new(storage: &'a mut [u8]) -> StackString<'a> {
+        StackString { storage, len: 0 }
+    }
+
+    /// Constructs a new `StackString` using `storage` as the backing store.
+    /// The first `len` bytes of `storage` are the initial contents.
+    ///
+    /// # Errors
+    ///
+    /// Returns `OutOfBounds` if `len > storage.len()` and `InvalidUtf8` if
+    /// the first `len` bytes aren't valid UTF-8.
+    pub fn from_utf8(storage: &'a mut [u8], len: usize) -> Result<StackString<'a>, Error> {
+        if len > storage.len() {
+            return Err(Error::OutOfBounds);
+        }
+        str::from_utf8(&storage[..len]).map_err(|_| Error::InvalidUtf8)?;
+        Ok(StackString { storage, len })
+    }
+
+    /// Returns the number of bytes this string can hold.
+    pub fn capacity(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// Extracts a string slice containing the entire string.
+    pub fn as_str(&self) -> &str {
+        unsafe { str::from_utf8_unchecked(&self.storage[..self.len]) }
+    }
+
+    /// Extracts a mutable string slice containing the entire string.
+    pub fn as_mut_str(&mut self) -> &mut str {
+        unsafe { str::from_utf8_unchecked_mut(&mut self.storage[..self.len]) }
+    }
+
+    /// Extracts a string slice containing the entire string, consuming
+    /// `self`.
+    pub fn into_str(self) -> &'a mut str {
+        let StackString { storage, len } = self;
+        unsafe { str::from_utf8_unchecked_mut(&mut storage[..len]) }
+    }
+
+    /// Appends `string` to the back of this string. See
+    /// `StringLike::push_str`.
+    pub fn push_str(&mut self, string: &str) -> Result<(), Error> {
+        let end = self.len + string.len();
+        if end > self.storage.len() {
+            return Err(Error::Full);
+        }
+
+        self.storage[self.len..end].copy_from_slice(string.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+
+    /// Shortens this string to `len` bytes. See `StringLike::truncate`.
+    pub fn truncate(&mut self, len: usize) -> Result<(), Error> {
+        if len >= self.len {
+            return Ok(());
+        }
+        if !self.is_char_boundary(len) {
+            return Err(Error::NotCharBoundary);
+        }
+
+        self.len = len;
+        Ok(())
+    }
+}
+
+impl<'a> StringLike for StackString<'a> {
+    fn capacity(&self) -> usize {
+        StackString::capacity(self)
+    }
+
+    fn push_str(&mut self, string: &str) -> Result<(), Error> {
+        StackString::push_str(self, string)
+    }
+
+    fn truncate(&mut self, len: usize) -> Result<(), Error> {
+        StackString::truncate(self, len)
+    }
+}
+
+impl<'a> Deref for StackString<'a> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<'a> DerefMut for StackString<'a> {
+    fn deref_mut(&mut self) -> &mut str {
+        self.as_mut_str()
+    }
+}
+
+impl<'a> AsRef<str> for StackString<'a> {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<'a> fmt::Write for StackString<'a> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        self.push_str(string).map_err(|_| fmt::Error)
+    }
+
+    fn write_fmt(&mut self, args: fmt::Arguments) -> fmt::Result {
+        string_like::write_fmt(self, args)
+    }
+}
+
+impl<'a> fmt::Display for StackString<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl<'a> fmt::Debug for StackString<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl<'a, 'b> PartialEq<&'b str> for StackString<'a> {
+    fn eq(&self, other: &&'b str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl<'a> PartialEq<str> for StackString<'a> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+#[cfg(test)]
+mod stack_string_test {
+    use super::*;
+    use core::fmt::Write;
+
+    #[test]
+    fn test_push() {
+        let mut storage = [0u8; 8];
+        let mut s = StackString::new(&mut storage);
+        s.push_str("ab").expect("fits");
+        s.push('é').expect("fits");
+        assert_eq!(s, "abé");
+        assert_eq!(s.len(), 4);
+        assert_eq!(s.push_str("12345"), Err(Error::Full));
+        assert_eq!(s, "abé");
+        s.push_str("1234").expect("fits");
+        assert!(s.is_full());
+        assert_eq!(s.push('x'), Err(Error::Full));
+        assert_eq!(s.pop(), Some('4'));
+        assert_eq!(s.into_str(), "abé123");
+    }
+
+    #[test]
+    fn test_char_boundaries() {
+        let mut storage = [0u8; 16];
+        let mut s = StackString::new(&mut storage);
+        s.push_str("añb").expect("fits");
+        assert_eq!(s.truncate(2), Err(Error::NotCharBoundary));
+        assert_eq!(s.insert(2, 'x'), Err(Error::NotCharBoundary));
+        assert_eq!(s.remove(2), Err(Error::NotCharBoundary));
+        assert_eq!(s.remove(4), Err(Error::OutOfBounds));
+        assert_eq!(s.try_split_at(2), Err(Error::NotCharBoundary));
+        assert_eq!(s.try_split_at(9), Err(Error::OutOfBounds));
+        assert_eq!(s, "añb");
+
+        s.insert_str(1, "→").expect("fits");
+        assert_eq!(s, "a→ñb");
+        assert_eq!(s.remove(4), Ok('ñ'));
+        assert_eq!(s.try_split_at(4), Ok(("a→", "b")));
+        s.truncate(1).expect("on a boundary");
+        assert_eq!(s, "a");
+    }
+
+    #[test]
+    fn test_split_off_into() {
+        let (mut storage, mut rest_storage) = ([0u8; 16], [0u8; 4]);
+        let mut s = StackString::new(&mut storage);
+        let mut rest = StackString::new(&mut rest_storage);
+        s.push_str("echo hello").expect("fits");
+        assert_eq!(s.split_off_into(4, &mut rest), Err(Error::Full));
+        assert_eq!(s.split_off_into(5, &mut rest), Err(Error::Full));
+        s.split_off_into(6, &mut rest).expect("fits");
+        assert_eq!((s.as_str(), rest.as_str()), ("echo h", "ello"));
+    }
+
+    #[test]
+    fn test_write() {
+        let mut storage = [b'?'; 10];
+        let mut s = StackString::new(&mut storage);
+        let name = "ab";
+        write!(s, "{}-{}", 12, name).expect("fits");
+        assert_eq!(s, "12-ab");
+        assert!(write!(s, "{}", 123456).is_err());
+        assert_eq!(s, "12-ab");
+        assert!(write!(s, "-{}", 12345).is_err());
+        assert_eq!(s, "12-ab");
+
+        let mut storage = *b"ok\xff";
+        assert_eq!(StackString::from_utf8(&mut storage, 3).err(), Some(Error::InvalidUtf8));
+        assert_eq!(StackString::from_utf8(&mut storage, 4).err(), Some(Error::OutOfBounds));
+        assert_eq!(StackString::from_utf8(&mut storage, 2).expect("valid"), "ok");
+    }
+}
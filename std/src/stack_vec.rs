@@ -1,4 +1,10 @@
+use core::fmt;
+use core::mem::{self, ManuallyDrop, MaybeUninit};
 use core::ops::{Deref, DerefMut};
+use core::ptr;
+use core::slice;
+
+pub use crate::vec_like::{Drain, VecLike};
 
 /// A contiguous array type backed by a slice.
 ///
@@ -8,20 +14,20 @@ use core::ops::{Deref, DerefMut};
 /// result, `StackVec`'s capacity is _bounded_ by the user-supplied slice. This
 /// results in `push` being fallible: if `push` is called when the vector is
 /// full, an `Err` is returned.
+///
+/// The elements are owned by the `StackVec` and dropped with it. The rest of
+/// the `Vec` API is provided by the `VecLike` trait.
 pub struct StackVec<'a, T: 'a> {
-    storage: &'a mut [T],
+    storage: &'a mut [MaybeUninit<T>],
     len: usize
 }
 
-impl<'a, T: 'a> StackVec<'a, T> {
+impl<'a, T: Copy + 'a> StackVec<'a, T> {
     /// Constructs a new, empty `StackVec<T>` using `storage` as the backing
     /// store. The returned `StackVec` will be able to hold `storage.len()`
     /// values.
     pub fn new(storage: &'a mut [T]) -> StackVec<'a, T> {
-        StackVec{
-            storage: storage,
-            len: 0
-        }
+        StackVec::with_len(storage, 0)
     }
 
     /// Constructs a new `StackVec<T>` using `storage` as the backing store. The
@@ -36,11 +42,23 @@ impl<'a, T: 'a> StackVec<'a, T> {
         if len > storage.len(){
             panic!("len > storage.len()");
         }
-        else{
-            StackVec{
-                storage: storage,
-                len: len
-            }
+        // `T: Copy`, so values are moved out of `storage` by copy, and only
+        // initialized values are ever written to it.
+        let storage = unsafe { &mut *(storage as *mut [T] as *mut [MaybeUninit<T>]) };
+        StackVec{
+            storage,
+            len
+        }
+    }
+}
+
+impl<'a, T: 'a> StackVec<'a, T> {
+    /// Constructs a new, empty `StackVec<T>` using the uninitialized
+    /// `storage` as the backing store. Unlike `new`, this accepts any `T`.
+    pub fn from_uninit(storage: &'a mut [MaybeUninit<T>]) -> StackVec<'a, T> {
+        StackVec{
+            storage,
+            len: 0
         }
     }
 
@@ -49,31 +67,37 @@ impl<'a, T: 'a> StackVec<'a, T> {
         self.storage.len()
     }
 
-    /// Shortens the vector, keeping the first `len` elements. If `len` is
-    /// greater than the vector's current length, this has no effect. Note that
-    /// this method has no effect on the capacity of the vector.
+    /// Shortens the vector, keeping the first `len` elements and dropping the
+    /// others. If `len` is greater than the vector's current length, this has
+    /// no effect. Note that this method has no effect on the capacity of the
+    /// vector.
     pub fn truncate(&mut self, len: usize) {
-        if len < self.len{
-            self.len = len;
+        while self.len > len {
+            self.len -= 1;
+            unsafe { ptr::drop_in_place(self.storage[self.len].as_mut_ptr()) };
         }
     }
 
-    /// Extracts a slice containing the entire vector, consuming `self`.
+    /// Extracts a slice containing the entire vector, consuming `self`. The
+    /// elements are no longer dropped.
     ///
     /// Note that the returned slice's length will be the length of this vector,
     /// _not_ the length of the original backing storage.
     pub fn into_slice(self) -> &'a mut [T] {
-        &mut self.storage[0..self.len]
+        let mut this = ManuallyDrop::new(self);
+        let len = this.len;
+        let storage = mem::take(&mut this.storage);
+        unsafe { slice::from_raw_parts_mut(storage.as_mut_ptr() as *mut T, len) }
     }
 
     /// Extracts a slice containing the entire vector.
     pub fn as_slice(&self) -> &[T] {
-        & self.storage[0..self.len]
+        unsafe { slice::from_raw_parts(self.storage.as_ptr() as *const T, self.len) }
     }
 
     /// Extracts a mutable slice of the entire vector.
     pub fn as_mut_slice(&mut self) -> &mut [T] {
-        &mut self.storage[0..self.len]
+        unsafe { slice::from_raw_parts_mut(self.storage.as_mut_ptr() as *mut T, self.len) }
     }
 
     /// Returns the number of elements in the vector, also referred to as its
@@ -96,27 +120,25 @@ impl<'a, T: 'a> StackVec<'a, T> {
     ///
     /// # Error
     ///
-    /// If this vector is full, an `Err` is returned. Otherwise, `Ok` is
-    /// returned.
-    pub fn push(&mut self, value: T) -> Result<(), ()> {
+    /// If this vector is full, `value` is returned in an `Err`. Otherwise,
+    /// `Ok` is returned.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
         if !self.is_full(){
-            self.storage[self.len] = value;
+            self.storage[self.len] = MaybeUninit::new(value);
             self.len += 1;
             Ok(())
         }
         else{
-            Err(())
+            Err(value)
         }
     }
-}
 
-impl<'a, T: Clone + 'a> StackVec<'a, T> {
     /// If this vector is not empty, removes the last element from this vector
-    /// by cloning it and returns it. Otherwise returns `None`.
+    /// and returns it. Otherwise returns `None`.
     pub fn pop(&mut self) -> Option<T> {
         if !self.is_empty(){
             self.len -= 1;
-            Some(self.storage[self.len].clone())
+            Some(unsafe { self.storage[self.len].as_ptr().read() })
         }
         else{
             None
@@ -124,34 +146,179 @@ impl<'a, T: Clone + 'a> StackVec<'a, T> {
     }
 }
 
+impl<'a, T: 'a> VecLike<T> for StackVec<'a, T> {
+    fn capacity(&self) -> usize {
+        StackVec::capacity(self)
+    }
+
+    fn push(&mut self, value: T) -> Result<(), T> {
+        StackVec::push(self, value)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        StackVec::pop(self)
+    }
+
+    fn truncate(&mut self, len: usize) {
+        StackVec::truncate(self, len)
+    }
+}
+
+impl<'a, T: 'a> Drop for StackVec<'a, T> {
+    fn drop(&mut self) {
+        self.truncate(0);
+    }
+}
+
 impl<'a, T: 'a> Deref for StackVec<'a, T> {
     type Target = [T];
 
     fn deref(&self) -> &Self::Target {
-        &self.storage[0..self.len]
+        self.as_slice()
     }
 }
 
 impl<'a, T: 'a> DerefMut for StackVec<'a, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.storage[0..self.len]
+        self.as_mut_slice()
+    }
+}
+
+impl<'a, T: fmt::Debug + 'a> fmt::Debug for StackVec<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// By-value iterator over the elements of a `StackVec`.
+pub struct IntoIter<'a, T: 'a> {
+    storage: &'a mut [MaybeUninit<T>],
+    start: usize,
+    end: usize,
+}
+
+impl<'a, T: 'a> Iterator for IntoIter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+
+        self.start += 1;
+        Some(unsafe { self.storage[self.start - 1].as_ptr().read() })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.end - self.start, Some(self.end - self.start))
+    }
+}
+
+impl<'a, T: 'a> DoubleEndedIterator for IntoIter<'a, T> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+
+        self.end -= 1;
+        Some(unsafe { self.storage[self.end].as_ptr().read() })
+    }
+}
+
+impl<'a, T: 'a> Drop for IntoIter<'a, T> {
+    fn drop(&mut self) {
+        for _ in self {}
     }
 }
 
 impl<'a, T: 'a> IntoIterator for StackVec<'a, T> {
-    type Item = &'a T;
-    type IntoIter = ::core::slice::Iter<'a, T>;
+    type Item = T;
+    type IntoIter = IntoIter<'a, T>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.storage[0..self.len].into_iter()
+        let mut this = ManuallyDrop::new(self);
+        let end = this.len;
+        IntoIter { storage: mem::take(&mut this.storage), start: 0, end }
     }
 }
 
-impl<'a, T: 'a> IntoIterator for &'a StackVec<'a, T> {
-    type Item = &'a T;
-    type IntoIter = ::core::slice::Iter<'a, T>;
+impl<'b, 'a: 'b, T: 'a> IntoIterator for &'b StackVec<'a, T> {
+    type Item = &'b T;
+    type IntoIter = ::core::slice::Iter<'b, T>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.storage[0..self.len].into_iter()
+        self.as_slice().iter()
+    }
+}
+
+impl<'b, 'a: 'b, T: 'a> IntoIterator for &'b mut StackVec<'a, T> {
+    type Item = &'b mut T;
+    type IntoIter = ::core::slice::IterMut<'b, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.as_mut_slice().iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod stack_vec_test {
+    use super::*;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_push_pop() {
+        let mut storage = [0u8; 2];
+        let mut v = StackVec::new(&mut storage);
+        assert_eq!(v.push(1), Ok(()));
+        assert_eq!(v.push(2), Ok(()));
+        assert_eq!(v.push(3), Err(3));
+        assert_eq!(v.pop(), Some(2));
+        assert_eq!(&v[..], &[1]);
+        assert_eq!(v.into_slice(), &mut [1]);
+        assert_eq!(storage, [1, 2]);
+    }
+
+    #[test]
+    fn test_vec_like() {
+        let mut storage = [0u8; 8];
+        let mut v = StackVec::new(&mut storage);
+        v.extend_from_slice(&[1, 2, 3, 4, 5]).expect("fits");
+        assert_eq!(v.extend_from_slice(&[0; 4]), Err(&[0; 4][..]));
+        v.insert(1, 9).expect("fits");
+        assert_eq!(v.remove(0), 1);
+        assert_eq!(v.swap_remove(1), 2);
+        assert_eq!(&v[..], &[9, 5, 3, 4]);
+        v.retain(|x| x % 2 == 1);
+        assert_eq!(&v[..], &[9, 5, 3]);
+        assert_eq!(v.drain(..2).collect::<Vec<_>>(), vec![9, 5]);
+        assert_eq!(&v[..], &[3]);
+        v.clear();
+        assert!(v.is_empty());
+    }
+
+    #[test]
+    fn test_drop_and_into_iter() {
+        let rc = Rc::new(());
+        let mut storage: [MaybeUninit<Rc<()>>; 4] = unsafe { MaybeUninit::uninit().assume_init() };
+        {
+            let mut v = StackVec::from_uninit(&mut storage);
+            for _ in 0..4 {
+                v.push(rc.clone()).expect("fits");
+            }
+            assert_eq!(Rc::strong_count(&rc), 5);
+            assert!(v.pop().is_some());
+            v.truncate(2);
+            assert_eq!(Rc::strong_count(&rc), 3);
+        }
+        assert_eq!(Rc::strong_count(&rc), 1);
+
+        let mut v = StackVec::from_uninit(&mut storage);
+        v.extend_from_slice(&[rc.clone(), rc.clone(), rc.clone()]).expect("fits");
+        let mut iter = v.into_iter();
+        assert!(iter.next().is_some());
+        assert_eq!(Rc::strong_count(&rc), 3);
+        drop(iter);
+        assert_eq!(Rc::strong_count(&rc), 1);
     }
 }
@@ -0,0 +1,179 @@
+use core::fmt;
+use core::ops::DerefMut;
+
+/// An error from a `StringLike` operation. Failed operations leave the
+/// string unchanged, and so does a failed `write!`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The string doesn't have enough capacity left.
+    Full,
+    /// An index is past the end of the string.
+    OutOfBounds,
+    /// An index isn't on a `char` boundary.
+    NotCharBoundary,
+    /// The bytes aren't valid UTF-8.
+    InvalidUtf8,
+}
+
+impl Error {
+    /// Returns a short description of the error.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Error::Full => "string is full",
+            Error::OutOfBounds => "index out of bounds",
+            Error::NotCharBoundary => "index not on a char boundary",
+            Error::InvalidUtf8 => "invalid utf-8",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The `String`-like interface shared by `StackString` and `ArrayString`,
+/// strings of bounded capacity that require no memory allocation.
+///
+/// Implementors provide `capacity`, `push_str` and `truncate`; the other
+/// methods are built on them and on the `str` that implementors dereference
+/// to. Unlike `String`, none of the methods panic: they return an `Error`
+/// instead.
+pub trait StringLike: DerefMut<Target = str> {
+    /// Returns the number of bytes the string can hold.
+    fn capacity(&self) -> usize;
+
+    /// Appends `string` to the back of the string.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Full`, without appending anything, if `string` doesn't fit.
+    fn push_str(&mut self, string: &str) -> Result<(), Error>;
+
+    /// Shortens the string to `len` bytes. Has no effect if `len` is greater
+    /// than the string's length.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotCharBoundary` if `len` isn't on a `char` boundary.
+    fn truncate(&mut self, len: usize) -> Result<(), Error>;
+
+    /// Returns the number of bytes that can still be appended.
+    fn remaining(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    /// Returns true if the string is at capacity.
+    fn is_full(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Removes all the characters.
+    fn clear(&mut self) {
+        let _ = self.truncate(0);
+    }
+
+    /// Appends `ch` to the back of the string.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Full` if the UTF-8 encoding of `ch` doesn't fit.
+    fn push(&mut self, ch: char) -> Result<(), Error> {
+        self.push_str(ch.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Removes the last character and returns it, or `None` if the string is
+    /// empty.
+    fn pop(&mut self) -> Option<char> {
+        let ch = self.chars().next_back()?;
+        let len = self.len() - ch.len_utf8();
+        let _ = self.truncate(len);
+        Some(ch)
+    }
+
+    /// Inserts `string` at the byte index `index`, shifting the following
+    /// characters back.
+    ///
+    /// # Errors
+    ///
+    /// Returns `OutOfBounds` if `index > len`, `NotCharBoundary` if `index`
+    /// isn't on a `char` boundary, and `Full` if `string` doesn't fit.
+    fn insert_str(&mut self, index: usize, string: &str) -> Result<(), Error> {
+        check_index(self, index)?;
+        self.push_str(string)?;
+        // Both runs of bytes being rotated are whole `str`s.
+        unsafe { self.as_bytes_mut()[index..].rotate_right(string.len()) };
+        Ok(())
+    }
+
+    /// Inserts `ch` at the byte index `index`. See `insert_str`.
+    fn insert(&mut self, index: usize, ch: char) -> Result<(), Error> {
+        self.insert_str(index, ch.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Removes and returns the character at the byte index `index`, shifting
+    /// the following characters forward.
+    ///
+    /// # Errors
+    ///
+    /// Returns `OutOfBounds` if `index >= len` and `NotCharBoundary` if
+    /// `index` isn't on a `char` boundary.
+    fn remove(&mut self, index: usize) -> Result<char, Error> {
+        check_index(self, index)?;
+        let ch = self[index..].chars().next().ok_or(Error::OutOfBounds)?;
+        unsafe { self.as_bytes_mut()[index..].rotate_left(ch.len_utf8()) };
+        let len = self.len() - ch.len_utf8();
+        let _ = self.truncate(len);
+        Ok(ch)
+    }
+
+    /// Divides the string in two at the byte index `mid`. Unlike
+    /// `str::split_at`, this doesn't panic.
+    ///
+    /// # Errors
+    ///
+    /// Returns `OutOfBounds` if `mid > len` and `NotCharBoundary` if `mid`
+    /// isn't on a `char` boundary.
+    fn try_split_at(&self, mid: usize) -> Result<(&str, &str), Error> {
+        check_index(self, mid)?;
+        Ok(self.split_at(mid))
+    }
+
+    /// Moves the characters from the byte index `at` onwards to the back of
+    /// `other`, leaving the first `at` bytes in `self`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `OutOfBounds` if `at > len`, `NotCharBoundary` if `at` isn't
+    /// on a `char` boundary, and `Full` if the characters don't fit in
+    /// `other`.
+    fn split_off_into<S: StringLike + ?Sized>(&mut self, at: usize, other: &mut S) -> Result<(), Error> {
+        check_index(self, at)?;
+        other.push_str(&self[at..])?;
+        self.truncate(at)
+    }
+}
+
+/// Implements `fmt::Write::write_fmt` for `string`: the arguments are
+/// appended piece by piece, and the pieces appended before a failure are
+/// truncated away.
+pub(crate) fn write_fmt<S: StringLike + fmt::Write>(string: &mut S, args: fmt::Arguments) -> fmt::Result {
+    let len = string.len();
+    let result = fmt::write(string, args);
+    if result.is_err() {
+        let _ = string.truncate(len);
+    }
+    result
+}
+
+/// Checks that `index` is within `string` and on a `char` boundary.
+fn check_index<S: StringLike + ?Sized>(string: &S, index: usize) -> Result<(), Error> {
+    if index > string.len() {
+        Err(Error::OutOfBounds)
+    } else if !string.is_char_boundary(index) {
+        Err(Error::NotCharBoundary)
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+//! Synchronization primitives, safe across cores.
+//!
+//! Drivers for memory-mapped devices should exist exactly once: two owners of
+//! the same registers would undo each other's configuration. A `Lazy` static
+//! builds such a singleton the first time it is used:
+//!
+//! ```rust
+//! # use swift_std::sync::Lazy;
+//! # struct Timer;
+//! # impl Timer {
+//! #     fn new() -> Timer { Timer }
+//! #     fn read(&self) -> u64 { 0 }
+//! # }
+//! static TIMER: Lazy<Timer> = Lazy::new(Timer::new);
+//!
+//! let now = TIMER.read();
+//! ```
+//!
+//! `RwLock`, `Semaphore` and `WaitQueue` block their callers through the
+//! hook in `park`: until the kernel installs a scheduler there, waiting
+//! spins.
+//!
+//! Until the MMU is enabled, these primitives only work on a single core with
+//! IRQs masked: see `exclusive`.
+
+mod once;
+mod rwlock;
+mod semaphore;
+mod wait_queue;
+pub mod park;
+pub mod exclusive;
+
+pub use self::once::{Once, OnceCell, Lazy};
+pub use self::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use self::semaphore::Semaphore;
+pub use self::wait_queue::WaitQueue;
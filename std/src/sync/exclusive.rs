@@ -0,0 +1,199 @@
+//! Atomic read-modify-write operations that work before the MMU is on.
+//!
+//! On the Raspberry Pi, the exclusive loads and stores behind atomic
+//! read-modify-write operations only succeed on cacheable memory, i.e. once
+//! the MMU and data cache are enabled; before that, a compare-and-swap never
+//! succeeds and spins forever. The locks of this crate go through this
+//! module, which falls back to a plain load and store until `enable` is
+//! called.
+//!
+//! The fallback is only sound on a single core with IRQs masked: another
+//! core, or an interrupt handler taking the same lock, could otherwise run
+//! between the load and the store. `init.S` parks every core but the first,
+//! which runs with IRQs masked, and debug builds assert both conditions.
+//! Neither the boot loader nor the kernel enables the MMU yet, so both stay
+//! on the fallback. The code enabling it must call `enable` before starting
+//! other cores or unmasking IRQs.
+//!
+//! On the host, the operations are always exclusive.
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(cfg!(any(test, feature = "host")));
+
+/// Makes read-modify-write operations exclusive from now on. Must be called
+/// before any core but the first one is started.
+///
+/// # Safety
+///
+/// The MMU and data cache must be enabled, with the memory of every lock
+/// mapped cacheable.
+pub unsafe fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Returns true if read-modify-write operations are exclusive.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// An atomic type whose read-modify-write operations can fall back to a plain
+/// load and store.
+pub(crate) trait Atomic {
+    type Value: Copy + PartialEq;
+
+    fn load(&self, order: Ordering) -> Self::Value;
+    fn store(&self, value: Self::Value, order: Ordering);
+    fn compare_exchange(&self, current: Self::Value, new: Self::Value, success: Ordering, failure: Ordering)
+        -> Result<Self::Value, Self::Value>;
+}
+
+macro_rules! atomic {
+    ($atomic:ty, $value:ty) => {
+        impl Atomic for $atomic {
+            type Value = $value;
+
+            fn load(&self, order: Ordering) -> $value {
+                <$atomic>::load(self, order)
+            }
+
+            fn store(&self, value: $value, order: Ordering) {
+                <$atomic>::store(self, value, order)
+            }
+
+            fn compare_exchange(&self, current: $value, new: $value, success: Ordering, failure: Ordering)
+                -> Result<$value, $value>
+            {
+                <$atomic>::compare_exchange(self, current, new, success, failure)
+            }
+        }
+    }
+}
+
+atomic!(AtomicBool, bool);
+atomic!(AtomicUsize, usize);
+
+/// Returns true if nothing can run between a plain load and store: the
+/// current core is the first one and its IRQs are masked. Always true off
+/// the Pi.
+fn single_context() -> bool {
+    #[cfg(all(not(any(test, feature = "host")), target_arch = "aarch64"))]
+    unsafe {
+        let (daif, mpidr): (u64, u64);
+        core::arch::asm!("mrs {}, DAIF", out(reg) daif, options(nomem, nostack));
+        core::arch::asm!("mrs {}, MPIDR_EL1", out(reg) mpidr, options(nomem, nostack));
+        // Bit 7 of `DAIF` masks IRQs.
+        daif & (1 << 7) != 0 && mpidr & 0xff == 0
+    }
+
+    #[cfg(not(all(not(any(test, feature = "host")), target_arch = "aarch64")))]
+    true
+}
+
+/// Panics in debug builds if the fallback isn't sound. See `single_context`.
+fn debug_assert_single_context() {
+    debug_assert!(single_context(), "plain atomic operation on another core or with IRQs unmasked");
+}
+
+/// Like `compare_exchange` of the atomic types, with a plain load and store
+/// until `enable` is called.
+pub(crate) fn compare_exchange<A: Atomic>(atomic: &A, current: A::Value, new: A::Value, success: Ordering,
+                                          failure: Ordering) -> Result<A::Value, A::Value> {
+    if enabled() {
+        atomic.compare_exchange(current, new, success, failure)
+    } else {
+        debug_assert_single_context();
+        plain::compare_exchange(atomic, current, new, success, failure)
+    }
+}
+
+/// Like `AtomicUsize::fetch_add`, with a plain load and store until `enable`
+/// is called.
+pub(crate) fn fetch_add(atomic: &AtomicUsize, value: usize, order: Ordering) -> usize {
+    if enabled() {
+        atomic.fetch_add(value, order)
+    } else {
+        debug_assert_single_context();
+        plain::fetch_update(atomic, order, |old| old.wrapping_add(value))
+    }
+}
+
+/// Like `AtomicUsize::fetch_sub`, with a plain load and store until `enable`
+/// is called.
+pub(crate) fn fetch_sub(atomic: &AtomicUsize, value: usize, order: Ordering) -> usize {
+    if enabled() {
+        atomic.fetch_sub(value, order)
+    } else {
+        debug_assert_single_context();
+        plain::fetch_update(atomic, order, |old| old.wrapping_sub(value))
+    }
+}
+
+/// The fallback operations, made of a separate load and store.
+mod plain {
+    use super::*;
+
+    /// Returns the ordering of the load part of an operation ordered by
+    /// `order`.
+    fn load_order(order: Ordering) -> Ordering {
+        match order {
+            Ordering::Acquire | Ordering::AcqRel | Ordering::SeqCst => Ordering::Acquire,
+            _ => Ordering::Relaxed,
+        }
+    }
+
+    /// Returns the ordering of the store part of an operation ordered by
+    /// `order`.
+    fn store_order(order: Ordering) -> Ordering {
+        match order {
+            Ordering::Release | Ordering::AcqRel | Ordering::SeqCst => Ordering::Release,
+            _ => Ordering::Relaxed,
+        }
+    }
+
+    pub fn compare_exchange<A: Atomic>(atomic: &A, current: A::Value, new: A::Value, success: Ordering,
+                                       failure: Ordering) -> Result<A::Value, A::Value> {
+        let order = match load_order(failure) {
+            Ordering::Acquire => Ordering::Acquire,
+            _ => load_order(success),
+        };
+        let old = atomic.load(order);
+        if old != current {
+            return Err(old);
+        }
+        atomic.store(new, store_order(success));
+        Ok(old)
+    }
+
+    pub fn fetch_update<F: FnOnce(usize) -> usize>(atomic: &AtomicUsize, order: Ordering, f: F) -> usize {
+        let old = atomic.load(load_order(order));
+        atomic.store(f(old), store_order(order));
+        old
+    }
+}
+
+#[cfg(test)]
+mod exclusive_test {
+    use super::*;
+
+    #[test]
+    fn test_plain_compare_exchange() {
+        let lock = AtomicBool::new(false);
+        assert_eq!(plain::compare_exchange(&lock, false, true, Ordering::Acquire, Ordering::Relaxed), Ok(false));
+        assert_eq!(plain::compare_exchange(&lock, false, true, Ordering::Acquire, Ordering::Relaxed), Err(true));
+        assert!(lock.load(Ordering::Relaxed));
+
+        let state = AtomicUsize::new(3);
+        assert_eq!(plain::compare_exchange(&state, 2, 5, Ordering::SeqCst, Ordering::SeqCst), Err(3));
+        assert_eq!(plain::compare_exchange(&state, 3, 5, Ordering::SeqCst, Ordering::SeqCst), Ok(3));
+        assert_eq!(state.load(Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn test_plain_fetch_update() {
+        let counter = AtomicUsize::new(0);
+        assert_eq!(plain::fetch_update(&counter, Ordering::Release, |old| old.wrapping_sub(1)), 0);
+        assert_eq!(plain::fetch_update(&counter, Ordering::Release, |old| old.wrapping_add(2)), usize::MAX);
+        assert_eq!(counter.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,263 @@
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::hint;
+use core::mem;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::exclusive;
+
+const INCOMPLETE: usize = 0;
+const RUNNING: usize = 1;
+const COMPLETE: usize = 2;
+const POISONED: usize = 3;
+
+/// Runs an initialization routine once.
+///
+/// The first caller of `call_once` runs its closure; concurrent callers spin
+/// until it returns, and later callers return immediately. Like `Mutex`, it
+/// doesn't need exclusive loads and stores before the MMU is enabled: see
+/// `exclusive`.
+pub struct Once {
+    state: AtomicUsize,
+}
+
+impl Once {
+    /// Returns a `Once` whose closure hasn't run yet.
+    pub const fn new() -> Once {
+        Once { state: AtomicUsize::new(INCOMPLETE) }
+    }
+
+    /// Runs `f` if no call to `call_once` has yet. Returns once `f`, or the
+    /// closure of the call that got there first, has returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the closure of an earlier call panicked.
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        loop {
+            match exclusive::compare_exchange(&self.state, INCOMPLETE, RUNNING, Ordering::Acquire, Ordering::Acquire) {
+                Ok(_) => {
+                    // Poisons the `Once` if `f` unwinds.
+                    let finish = Finish { state: &self.state };
+                    f();
+                    finish.state.store(COMPLETE, Ordering::Release);
+                    mem::forget(finish);
+                    return;
+                }
+                Err(COMPLETE) => return,
+                Err(POISONED) => panic!("Once instance has previously been poisoned"),
+                Err(_) => {
+                    while self.state.load(Ordering::Acquire) == RUNNING {
+                        hint::spin_loop();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns true if a closure passed to `call_once` has returned.
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+}
+
+impl Default for Once {
+    fn default() -> Once {
+        Once::new()
+    }
+}
+
+impl fmt::Debug for Once {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Once").field("completed", &self.is_completed()).finish()
+    }
+}
+
+/// Marks a `Once` poisoned when dropped, i.e. when its closure unwinds.
+struct Finish<'a> {
+    state: &'a AtomicUsize,
+}
+
+impl<'a> Drop for Finish<'a> {
+    fn drop(&mut self) {
+        self.state.store(POISONED, Ordering::Release);
+    }
+}
+
+/// A cell that is written at most once.
+pub struct OnceCell<T> {
+    once: Once,
+    value: UnsafeCell<Option<T>>,
+}
+
+unsafe impl<T: Send> Send for OnceCell<T> {}
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    /// Returns an empty cell.
+    pub const fn new() -> OnceCell<T> {
+        OnceCell { once: Once::new(), value: UnsafeCell::new(None) }
+    }
+
+    /// Returns the value, or `None` if the cell is empty.
+    pub fn get(&self) -> Option<&T> {
+        if self.once.is_completed() {
+            unsafe { (*self.value.get()).as_ref() }
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value mutably, or `None` if the cell is empty.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        unsafe { (*self.value.get()).as_mut() }
+    }
+
+    /// Stores `value` in the cell.
+    ///
+    /// # Errors
+    ///
+    /// Returns `value` back if the cell was already written.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(|| value.take().unwrap());
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
+    /// Returns the value, storing the one returned by `f` first if the cell
+    /// is empty. `f` runs at most once, even if several cores call this at
+    /// the same time.
+    pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+        self.once.call_once(|| unsafe { *self.value.get() = Some(f()) });
+        match self.get() {
+            Some(value) => value,
+            None => unreachable!(),
+        }
+    }
+
+    /// Consumes the cell and returns its value, if any.
+    pub fn into_inner(self) -> Option<T> {
+        self.value.into_inner()
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> OnceCell<T> {
+        OnceCell::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("OnceCell").field(value).finish(),
+            None => f.write_str("OnceCell(<uninit>)"),
+        }
+    }
+}
+
+/// A value built by `F` the first time it is dereferenced.
+pub struct Lazy<T, F = fn() -> T> {
+    cell: OnceCell<T>,
+    init: UnsafeCell<Option<F>>,
+}
+
+unsafe impl<T: Send + Sync, F: Send> Sync for Lazy<T, F> {}
+
+impl<T, F> Lazy<T, F> {
+    /// Returns a `Lazy` that will be built by `init`.
+    pub const fn new(init: F) -> Lazy<T, F> {
+        Lazy { cell: OnceCell::new(), init: UnsafeCell::new(Some(init)) }
+    }
+}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    /// Builds the value if it isn't yet, and returns it.
+    pub fn force(this: &Lazy<T, F>) -> &T {
+        this.cell.get_or_init(|| {
+            // Only the single caller that runs the `Once` gets here.
+            match unsafe { (*this.init.get()).take() } {
+                Some(init) => init(),
+                None => unreachable!(),
+            }
+        })
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        Lazy::force(self)
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for Lazy<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Lazy").field("cell", &self.cell).finish()
+    }
+}
+
+#[cfg(test)]
+mod once_test {
+    use super::*;
+    use std::panic;
+    use std::thread;
+
+    #[test]
+    fn test_once() {
+        let once = Once::new();
+        let mut calls = 0;
+        assert!(!once.is_completed());
+        once.call_once(|| calls += 1);
+        once.call_once(|| calls += 1);
+        assert!(once.is_completed());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_once_poisoned() {
+        let once = Once::new();
+        let result = panic::catch_unwind(|| once.call_once(|| panic!("init failed")));
+        assert!(result.is_err());
+        assert!(!once.is_completed());
+        assert!(panic::catch_unwind(|| once.call_once(|| ())).is_err());
+    }
+
+    #[test]
+    fn test_once_cell() {
+        let mut cell = OnceCell::new();
+        assert_eq!(cell.get(), None);
+        assert_eq!(cell.get_or_init(|| 1), &1);
+        assert_eq!(cell.get_or_init(|| 2), &1);
+        assert_eq!(cell.set(3), Err(3));
+        *cell.get_mut().expect("set") += 1;
+        assert_eq!(cell.into_inner(), Some(2));
+
+        let cell = OnceCell::new();
+        assert_eq!(cell.set("ok"), Ok(()));
+        assert_eq!(cell.get(), Some(&"ok"));
+    }
+
+    static INITS: AtomicUsize = AtomicUsize::new(0);
+    static DEVICE: Lazy<usize> = Lazy::new(|| {
+        // Leave time for other threads to race the initialization.
+        thread::yield_now();
+        INITS.fetch_add(1, Ordering::SeqCst) + 42
+    });
+
+    #[test]
+    fn test_lazy_across_threads() {
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| assert_eq!(*DEVICE, 42));
+            }
+        });
+        assert_eq!(*DEVICE, 42);
+        assert_eq!(INITS.load(Ordering::SeqCst), 1);
+    }
+}
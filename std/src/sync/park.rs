@@ -0,0 +1,60 @@
+//! The hook through which waiting contexts give up the CPU.
+//!
+//! Without a scheduler, a context waiting on a `WaitQueue` can only spin,
+//! which is what `Spin` does. Once the kernel has a scheduler, it installs a
+//! `Park` implementation with `install` that puts waiting threads to sleep
+//! and wakes them up when the queue is notified.
+
+use core::hint;
+
+use super::once::OnceCell;
+use super::wait_queue::WaitQueue;
+
+/// Blocks and wakes up contexts waiting on a `WaitQueue`.
+///
+/// A queue is identified by its address. Every notification of a queue
+/// increments its epoch before calling `unpark`, so an implementation avoids
+/// lost wake-ups by only going to sleep while the epoch is still the one the
+/// waiter read, checked under the same lock that `unpark` takes.
+pub trait Park: Sync {
+    /// Blocks the current context while `queue.epoch() == epoch`, until
+    /// `unpark` is called for `queue`. May return early: callers check their
+    /// condition again and park again if needed.
+    fn park(&self, queue: &WaitQueue, epoch: usize);
+
+    /// Wakes up one context parked on `queue`, or all of them if `all` is
+    /// true.
+    fn unpark(&self, queue: &WaitQueue, all: bool);
+}
+
+/// The fallback when no scheduler is installed: parking spins once and
+/// returns, and unparking does nothing.
+pub struct Spin;
+
+impl Park for Spin {
+    fn park(&self, _queue: &WaitQueue, _epoch: usize) {
+        hint::spin_loop();
+    }
+
+    fn unpark(&self, _queue: &WaitQueue, _all: bool) {}
+}
+
+static HOOK: OnceCell<&'static dyn Park> = OnceCell::new();
+
+/// Installs `hook` for all wait queues.
+///
+/// # Errors
+///
+/// Returns `hook` back if a hook is already installed; it can only be
+/// installed once.
+pub fn install(hook: &'static dyn Park) -> Result<(), &'static dyn Park> {
+    HOOK.set(hook)
+}
+
+/// Returns the installed hook, or `Spin` if none is.
+pub fn current() -> &'static dyn Park {
+    match HOOK.get() {
+        Some(hook) => *hook,
+        None => &Spin,
+    }
+}
@@ -0,0 +1,191 @@
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::exclusive;
+use super::wait_queue::WaitQueue;
+
+/// The `state` of a write-locked `RwLock`. Other states count the readers.
+const WRITER: usize = usize::MAX;
+
+/// A reader-writer lock protecting a `T`.
+///
+/// Any number of readers or a single writer may hold the lock at a time.
+/// Callers that can't take it wait on a `WaitQueue`. Readers are preferred:
+/// a steady stream of readers can keep a writer waiting.
+pub struct RwLock<T> {
+    data: UnsafeCell<T>,
+    state: AtomicUsize,
+    queue: WaitQueue,
+}
+
+unsafe impl<T: Send> Send for RwLock<T> { }
+unsafe impl<T: Send + Sync> Sync for RwLock<T> { }
+
+/// Shared read access to the value of a `RwLock`, released when dropped.
+pub struct RwLockReadGuard<'a, T: 'a> {
+    lock: &'a RwLock<T>
+}
+
+impl<'a, T> !Send for RwLockReadGuard<'a, T> { }
+unsafe impl<'a, T: Sync> Sync for RwLockReadGuard<'a, T> { }
+
+/// Exclusive write access to the value of a `RwLock`, released when
+/// dropped.
+pub struct RwLockWriteGuard<'a, T: 'a> {
+    lock: &'a RwLock<T>
+}
+
+impl<'a, T> !Send for RwLockWriteGuard<'a, T> { }
+unsafe impl<'a, T: Sync> Sync for RwLockWriteGuard<'a, T> { }
+
+impl<T> RwLock<T> {
+    /// Returns an unlocked `RwLock` protecting `val`.
+    pub const fn new(val: T) -> RwLock<T> {
+        RwLock {
+            data: UnsafeCell::new(val),
+            state: AtomicUsize::new(0),
+            queue: WaitQueue::new(),
+        }
+    }
+
+    /// Consumes the lock and returns the protected value.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    /// Returns a mutable reference to the protected value. No locking is
+    /// needed since `self` is borrowed mutably.
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
+
+    /// Acquires shared read access, waiting while a writer holds the lock.
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.queue.wait_until(|| self.try_read())
+    }
+
+    /// Acquires shared read access if no writer holds the lock, or returns
+    /// `None` without blocking.
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        let mut state = self.state.load(Ordering::Relaxed);
+        // The reader count stops one short of `WRITER`.
+        while state < WRITER - 1 {
+            match exclusive::compare_exchange(&self.state, state, state + 1, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => return Some(RwLockReadGuard { lock: self }),
+                Err(current) => state = current,
+            }
+        }
+        None
+    }
+
+    /// Acquires exclusive write access, waiting while anyone holds the lock.
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.queue.wait_until(|| self.try_write())
+    }
+
+    /// Acquires exclusive write access if nobody holds the lock, or returns
+    /// `None` without blocking.
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        exclusive::compare_exchange(&self.state, 0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| RwLockWriteGuard { lock: self })
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> RwLock<T> {
+        RwLock::new(T::default())
+    }
+}
+
+impl<'a, T: 'a> Deref for RwLockReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { & *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: 'a> Drop for RwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        // Only a writer can be waiting, for the last reader.
+        if exclusive::fetch_sub(&self.lock.state, 1, Ordering::Release) == 1 {
+            self.lock.queue.notify_one();
+        }
+    }
+}
+
+impl<'a, T: 'a> Deref for RwLockWriteGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { & *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: 'a> DerefMut for RwLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: 'a> Drop for RwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.state.store(0, Ordering::Release);
+        self.lock.queue.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod rwlock_test {
+    use super::*;
+    use crate::sync::wait_queue::wait_queue_test::install_host_park;
+    use std::thread;
+
+    #[test]
+    fn test_try_lock() {
+        let lock = RwLock::new(1);
+        {
+            let (first, second) = (lock.read(), lock.try_read().expect("shared"));
+            assert_eq!(*first + *second, 2);
+            assert!(lock.try_write().is_none());
+        }
+        {
+            let mut writer = lock.try_write().expect("unlocked");
+            assert!(lock.try_read().is_none());
+            assert!(lock.try_write().is_none());
+            *writer += 1;
+        }
+        assert_eq!(*lock.read(), 2);
+        assert_eq!(lock.into_inner(), 2);
+    }
+
+    #[test]
+    fn test_readers_and_writers() {
+        install_host_park();
+        // Writers keep both halves equal; readers must never see them differ.
+        let lock = RwLock::new((0usize, 0usize));
+        thread::scope(|scope| {
+            for _ in 0..2 {
+                scope.spawn(|| {
+                    for _ in 0..500 {
+                        let mut pair = lock.write();
+                        pair.0 += 1;
+                        thread::yield_now();
+                        pair.1 += 1;
+                    }
+                });
+            }
+            for _ in 0..3 {
+                scope.spawn(|| {
+                    for _ in 0..500 {
+                        let pair = lock.read();
+                        assert_eq!(pair.0, pair.1);
+                    }
+                });
+            }
+        });
+        assert_eq!(*lock.read(), (1000, 1000));
+    }
+}
@@ -0,0 +1,96 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::exclusive;
+use super::wait_queue::WaitQueue;
+
+/// A counting semaphore.
+///
+/// `acquire` takes one of a number of permits, waiting on a `WaitQueue`
+/// while there is none left, and `release` returns one. Permits aren't tied
+/// to whoever acquired them: an interrupt handler may `release` a permit for
+/// a thread to `acquire`.
+pub struct Semaphore {
+    permits: AtomicUsize,
+    queue: WaitQueue,
+}
+
+impl Semaphore {
+    /// Returns a semaphore with `permits` permits available.
+    pub const fn new(permits: usize) -> Semaphore {
+        Semaphore { permits: AtomicUsize::new(permits), queue: WaitQueue::new() }
+    }
+
+    /// Returns the number of permits available. It may change as soon as it
+    /// is returned.
+    pub fn available_permits(&self) -> usize {
+        self.permits.load(Ordering::Relaxed)
+    }
+
+    /// Takes a permit if one is available and returns true, or returns false
+    /// without blocking.
+    pub fn try_acquire(&self) -> bool {
+        let mut permits = self.permits.load(Ordering::Relaxed);
+        while permits > 0 {
+            match exclusive::compare_exchange(&self.permits, permits, permits - 1, Ordering::Acquire,
+                                              Ordering::Relaxed) {
+                Ok(_) => return true,
+                Err(current) => permits = current,
+            }
+        }
+        false
+    }
+
+    /// Takes a permit, waiting until one is available.
+    pub fn acquire(&self) {
+        self.queue.wait_until(|| if self.try_acquire() { Some(()) } else { None })
+    }
+
+    /// Returns a permit and wakes up a waiter.
+    pub fn release(&self) {
+        exclusive::fetch_add(&self.permits, 1, Ordering::Release);
+        self.queue.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod semaphore_test {
+    use super::*;
+    use crate::sync::wait_queue::wait_queue_test::install_host_park;
+    use std::thread;
+
+    #[test]
+    fn test_permits() {
+        let semaphore = Semaphore::new(2);
+        assert!(semaphore.try_acquire());
+        semaphore.acquire();
+        assert!(!semaphore.try_acquire());
+        assert_eq!(semaphore.available_permits(), 0);
+        semaphore.release();
+        assert_eq!(semaphore.available_permits(), 1);
+        semaphore.acquire();
+        assert!(!semaphore.try_acquire());
+    }
+
+    #[test]
+    fn test_bounds_concurrency() {
+        install_host_park();
+        let semaphore = Semaphore::new(2);
+        let (inside, max_inside) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        thread::scope(|scope| {
+            for _ in 0..6 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        semaphore.acquire();
+                        let now = inside.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_inside.fetch_max(now, Ordering::SeqCst);
+                        thread::yield_now();
+                        inside.fetch_sub(1, Ordering::SeqCst);
+                        semaphore.release();
+                    }
+                });
+            }
+        });
+        assert!(max_inside.load(Ordering::SeqCst) <= 2);
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+}
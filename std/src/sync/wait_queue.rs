@@ -0,0 +1,158 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::mutex::{Mutex, MutexGuard};
+use super::exclusive;
+use super::park;
+
+/// A queue of contexts waiting for a condition to become true, in the
+/// manner of a condition variable.
+///
+/// Waiters check their condition and park through `park::current()` until
+/// it holds; whoever makes it true calls `notify_one` or `notify_all`.
+pub struct WaitQueue {
+    epoch: AtomicUsize,
+}
+
+impl WaitQueue {
+    /// Returns a queue with no waiters.
+    pub const fn new() -> WaitQueue {
+        WaitQueue { epoch: AtomicUsize::new(0) }
+    }
+
+    /// Returns the number of notifications so far, wrapping around. Used by
+    /// `Park` implementations to avoid lost wake-ups.
+    pub fn epoch(&self) -> usize {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    /// Blocks until `condition` returns `Some`, and returns its value.
+    /// `condition` is called again each time the queue is notified, and
+    /// possibly more often.
+    pub fn wait_until<R, F: FnMut() -> Option<R>>(&self, mut condition: F) -> R {
+        loop {
+            // Read before checking, so that a notification between the check
+            // and parking changes the epoch and parking returns.
+            let epoch = self.epoch();
+            if let Some(value) = condition() {
+                return value;
+            }
+            park::current().park(self, epoch);
+        }
+    }
+
+    /// Blocks while `condition` returns true for the value protected by
+    /// `guard`'s mutex. The mutex is released while parked and held again
+    /// when `condition` is called and when this returns.
+    pub fn wait_while<'a, T, F>(&self, guard: MutexGuard<'a, T>, mut condition: F) -> MutexGuard<'a, T>
+        where F: FnMut(&mut T) -> bool
+    {
+        let mutex: &'a Mutex<T> = MutexGuard::mutex(&guard);
+        let mut guard = guard;
+        while condition(&mut guard) {
+            // Notifiers change the value under the lock, so the epoch is
+            // read before they can.
+            let epoch = self.epoch();
+            drop(guard);
+            park::current().park(self, epoch);
+            guard = mutex.lock();
+        }
+        guard
+    }
+
+    /// Wakes up one waiter.
+    pub fn notify_one(&self) {
+        exclusive::fetch_add(&self.epoch, 1, Ordering::Release);
+        park::current().unpark(self, false);
+    }
+
+    /// Wakes up all the waiters.
+    pub fn notify_all(&self) {
+        exclusive::fetch_add(&self.epoch, 1, Ordering::Release);
+        park::current().unpark(self, true);
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> WaitQueue {
+        WaitQueue::new()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod wait_queue_test {
+    use super::*;
+    use crate::sync::park::Park;
+    use std::sync::{Condvar, Mutex as HostMutex};
+    use std::thread;
+
+    /// Parks host threads on a condition variable shared by all queues.
+    pub(crate) struct HostPark {
+        lock: HostMutex<()>,
+        condvar: Condvar,
+    }
+
+    impl Park for HostPark {
+        fn park(&self, queue: &WaitQueue, epoch: usize) {
+            let mut lock = self.lock.lock().unwrap();
+            while queue.epoch() == epoch {
+                lock = self.condvar.wait(lock).unwrap();
+            }
+        }
+
+        fn unpark(&self, _queue: &WaitQueue, _all: bool) {
+            let _lock = self.lock.lock().unwrap();
+            self.condvar.notify_all();
+        }
+    }
+
+    static HOST_PARK: HostPark = HostPark { lock: HostMutex::new(()), condvar: Condvar::new() };
+
+    /// Makes waiting tests sleep rather than spin, which is slow on hosts
+    /// with few cores. Waiting works the same without it.
+    pub(crate) fn install_host_park() {
+        let _ = park::install(&HOST_PARK);
+    }
+
+    #[test]
+    fn test_wait_until() {
+        install_host_park();
+        let queue = WaitQueue::new();
+        let mailbox = Mutex::new(None);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for i in 1..100 {
+                    queue.wait_until(|| match *mailbox.lock() {
+                        None => Some(()),
+                        Some(_) => None,
+                    });
+                    *mailbox.lock() = Some(i);
+                    queue.notify_all();
+                }
+            });
+
+            for i in 1..100 {
+                let received = queue.wait_until(|| mailbox.lock().take());
+                queue.notify_all();
+                assert_eq!(received, i);
+            }
+        });
+    }
+
+    #[test]
+    fn test_wait_while() {
+        install_host_park();
+        let queue = WaitQueue::new();
+        let count = Mutex::new(0);
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    *count.lock() += 1;
+                    queue.notify_all();
+                });
+            }
+
+            let guard = queue.wait_while(count.lock(), |count| *count < 4);
+            assert_eq!(*guard, 4);
+        });
+    }
+}
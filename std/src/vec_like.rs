@@ -0,0 +1,166 @@
+use core::ops::{Bound, DerefMut, RangeBounds};
+
+/// The `Vec`-like interface shared by `StackVec` and `ArrayVec`, vectors of
+/// bounded capacity that require no memory allocation.
+///
+/// Implementors provide `capacity`, `push`, `pop` and `truncate`; the other
+/// methods are built on them and on the slice of the elements that
+/// implementors dereference to.
+pub trait VecLike<T>: DerefMut<Target = [T]> {
+    /// Returns the number of elements the vector can hold.
+    fn capacity(&self) -> usize;
+
+    /// Appends `value` to the back of the vector.
+    ///
+    /// # Errors
+    ///
+    /// Returns `value` back if the vector is full.
+    fn push(&mut self, value: T) -> Result<(), T>;
+
+    /// Removes the last element and returns it, or `None` if the vector is
+    /// empty.
+    fn pop(&mut self) -> Option<T>;
+
+    /// Shortens the vector to `len` elements, dropping the others. Has no
+    /// effect if `len` is greater than the vector's length.
+    fn truncate(&mut self, len: usize);
+
+    /// Returns true if the vector is at capacity.
+    fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Removes all the elements.
+    fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Inserts `value` at `index`, shifting the following elements back.
+    ///
+    /// # Errors
+    ///
+    /// Returns `value` back if the vector is full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    fn insert(&mut self, index: usize, value: T) -> Result<(), T> {
+        assert!(index <= self.len(), "insertion index (is {}) should be <= len (is {})", index, self.len());
+        self.push(value)?;
+        self[index..].rotate_right(1);
+        Ok(())
+    }
+
+    /// Removes and returns the element at `index`, shifting the following
+    /// elements forward.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index >= len`.
+    fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len(), "removal index (is {}) should be < len (is {})", index, self.len());
+        self[index..].rotate_left(1);
+        self.pop().unwrap()
+    }
+
+    /// Removes and returns the element at `index`, replacing it with the last
+    /// element. Doesn't preserve the order of the elements, but is O(1).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index >= len`.
+    fn swap_remove(&mut self, index: usize) -> T {
+        assert!(index < self.len(), "swap_remove index (is {}) should be < len (is {})", index, self.len());
+        let last = self.len() - 1;
+        self.swap(index, last);
+        self.pop().unwrap()
+    }
+
+    /// Keeps only the elements for which `f` returns true, in their order.
+    fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        let mut kept = 0;
+        for i in 0..self.len() {
+            if f(&self[i]) {
+                self.swap(kept, i);
+                kept += 1;
+            }
+        }
+        self.truncate(kept);
+    }
+
+    /// Appends clones of the elements of `other`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `other` as an error, without appending anything, if it
+    /// doesn't fit.
+    fn extend_from_slice<'a>(&mut self, other: &'a [T]) -> Result<(), &'a [T]> where T: Clone {
+        if other.len() > self.capacity() - self.len() {
+            return Err(other);
+        }
+
+        for value in other {
+            let _ = self.push(value.clone());
+        }
+        Ok(())
+    }
+
+    /// Removes the elements in `range` and returns them, in order, from an
+    /// iterator. The elements the iterator doesn't yield are dropped with
+    /// it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is decreasing or goes past the end of the vector.
+    fn drain<R: RangeBounds<usize>>(&mut self, range: R) -> Drain<'_, Self, T> where Self: Sized {
+        let len = self.len();
+        let start = match range.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&n) => n + 1,
+            Bound::Excluded(&n) => n,
+            Bound::Unbounded => len,
+        };
+        assert!(start <= end && end <= len, "drain range {}..{} should be within len (is {})", start, end, len);
+
+        // Move the drained elements to the back, last one first, so that
+        // `pop` yields them in order.
+        self[start..].rotate_left(end - start);
+        self[len - (end - start)..].reverse();
+        Drain { vec: self, remaining: end - start, _element: ::core::marker::PhantomData }
+    }
+}
+
+/// Iterator over the elements removed by `VecLike::drain`.
+pub struct Drain<'v, V: VecLike<T> + 'v, T> {
+    vec: &'v mut V,
+    remaining: usize,
+    _element: ::core::marker::PhantomData<T>,
+}
+
+impl<'v, V: VecLike<T>, T> Iterator for Drain<'v, V, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.remaining -= 1;
+        self.vec.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'v, V: VecLike<T>, T> Drop for Drain<'v, V, T> {
+    fn drop(&mut self) {
+        let len = self.vec.len();
+        self.vec.truncate(len - self.remaining);
+    }
+}
@@ -2,8 +2,10 @@ use core::prelude::v1::*;
 
 mod traits;
 mod macros;
+mod register;
 
 pub use crate::volatile::traits::*;
+pub use crate::volatile::register::*;
 use crate::volatile::macros::*;
 
 /// Reexports all of the traits in this crate.
@@ -12,11 +14,11 @@ use crate::volatile::macros::*;
 /// adding a glob import to the top of wrapper-heavy modules:
 ///
 /// ```rust
-/// use volatile::prelude::*;
+/// use swift_std::volatile::prelude::*;
 /// ```
 pub mod prelude {
 	#[doc(no_inline)]
-    pub use super::{Readable, Writeable, ReadableWriteable, Wrapper};
+    pub use super::{Readable, Writeable, ReadableWriteable, Wrapper, Register};
 }
 
 /// A wrapper type that enforces **read-only** _volatile_ accesses to a raw
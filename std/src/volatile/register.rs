@@ -0,0 +1,361 @@
+//! Typed register values with named bit fields.
+//!
+//! `register!` declares a newtype over a register's integer with a getter and
+//! a setter per field, so that drivers name fields instead of building masks:
+//!
+//! ```rust,ignore
+//! register! {
+//!     /// The mini UART line control register, `AUX_MU_LCR_REG`.
+//!     struct Lcr(u8) {
+//!         /// The number of data bits.
+//!         data_size, set_data_size: 1, 0 => DataSize;
+//!         /// Gives access to the baud rate registers.
+//!         dlab, set_dlab: 7, 7 => bool;
+//!     }
+//! }
+//!
+//! registers.MU_LCR.modify(|lcr| lcr.set_data_size(DataSize::EightBit));
+//! let eight_bits = registers.MU_LCR.read().data_size() == Some(DataSize::EightBit);
+//! ```
+//!
+//! A field is declared as `getter, setter: msb, lsb`, its most and least
+//! significant bits inclusive, as datasheets write them. A read-only field
+//! has `_` in place of the setter and a write-only field `_` in place of the
+//! getter. `[n]` before the bits declares `n` consecutive fields of the same
+//! width, accessed by index: `function, set_function: [10] 2, 0` is a field of
+//! bits 2..0 for index 0, bits 5..3 for index 1, and so on.
+//!
+//! A field's getter returns the register's integer, or with `=> T`, the
+//! `FieldValue::Read` of `T`: a `bool` for `bool`, and `Option<T>` for an
+//! enum declared with `field_enum!`, which is `None` for encodings with no
+//! variant. Setters consume the value and return it, so that they chain.
+//! The whole integer is converted with `Register::from_bits` and
+//! `Register::bits`, and `Default` is all bits clear.
+//!
+//! The value types are plain integers in memory: wrapped as `Volatile<Lcr>`,
+//! a register is read, written and modified with `Readable::read`,
+//! `Writeable::write` and `Writeable::modify`.
+
+/// An integer a register holds.
+pub trait Bits: Copy {
+    /// Returns the `width` bits of `self` starting at bit `lsb`.
+    fn field(self, lsb: usize, width: usize) -> Self;
+
+    /// Returns `self` with the `width` bits starting at bit `lsb` replaced by
+    /// `value`.
+    ///
+    /// # Panics
+    ///
+    /// Panics in debug builds if `value` doesn't fit in `width` bits.
+    fn with_field(self, lsb: usize, width: usize, value: Self) -> Self;
+}
+
+macro impl_bits($($int:ty),*) {
+    $(impl Bits for $int {
+        #[inline(always)]
+        fn field(self, lsb: usize, width: usize) -> $int {
+            (self >> lsb) & mask::<$int>(width)
+        }
+
+        #[inline(always)]
+        fn with_field(self, lsb: usize, width: usize, value: $int) -> $int {
+            debug_assert!(value & !mask::<$int>(width) == 0,
+                          "field value {:#x} is wider than {} bits", value, width);
+            let mask = mask::<$int>(width) << lsb;
+            (self & !mask) | ((value << lsb) & mask)
+        }
+    })*
+}
+
+/// Returns an integer with its `width` low bits set.
+#[inline(always)]
+fn mask<T>(width: usize) -> T
+    where T: Bits + ::core::ops::Not<Output = T> + ::core::ops::Shl<usize, Output = T> + From<u8>
+{
+    if width >= 8 * ::core::mem::size_of::<T>() {
+        !T::from(0)
+    } else {
+        !(!T::from(0) << width)
+    }
+}
+
+impl_bits!(u8, u16, u32, u64);
+
+/// A register value type, declared with `register!`.
+pub trait Register: Copy {
+    /// The register's integer.
+    type Bits: Bits;
+
+    /// Returns the value with bits `bits`.
+    fn from_bits(bits: Self::Bits) -> Self;
+
+    /// Returns the bits of the value.
+    fn bits(self) -> Self::Bits;
+}
+
+/// A type a register field holds, encoded in the register's integer `T`.
+pub trait FieldValue<T>: Sized {
+    /// What reading the field returns.
+    type Read;
+
+    /// Decodes the bits of a field.
+    fn from_bits(bits: T) -> Self::Read;
+
+    /// Encodes `self` as the bits of a field.
+    fn into_bits(self) -> T;
+}
+
+macro impl_field_value($($int:ty),*) {
+    $(impl FieldValue<$int> for $int {
+        type Read = $int;
+        #[inline(always)] fn from_bits(bits: $int) -> $int { bits }
+        #[inline(always)] fn into_bits(self) -> $int { self }
+    }
+
+    impl FieldValue<$int> for bool {
+        type Read = bool;
+        #[inline(always)] fn from_bits(bits: $int) -> bool { bits != 0 }
+        #[inline(always)] fn into_bits(self) -> $int { self as $int }
+    })*
+}
+
+impl_field_value!(u8, u16, u32, u64);
+
+/// Declares a register value type with named bit fields. See the module
+/// documentation for the syntax.
+pub macro register {
+    ($(#[$attr:meta])* $vis:vis struct $name:ident($int:ty) { $($fields:tt)* }) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Default, PartialEq, Eq)]
+        #[repr(transparent)]
+        $vis struct $name($int);
+
+        impl $crate::volatile::Register for $name {
+            type Bits = $int;
+
+            #[inline(always)]
+            fn from_bits(bits: $int) -> $name {
+                $name(bits)
+            }
+
+            #[inline(always)]
+            fn bits(self) -> $int {
+                self.0
+            }
+        }
+
+        impl $name {
+            $crate::volatile::register_fields!($int; $($fields)*);
+        }
+
+        impl ::core::fmt::Debug for $name {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                write!(f, "{}({:#x})", stringify!($name), self.0)
+            }
+        }
+    }
+}
+
+#[doc(hidden)]
+pub macro register_fields {
+    ($int:ty;) => {},
+    ($int:ty; $(#[$attr:meta])* $($get:ident)? $(_)?, $($set:ident)? $(_)?:
+     $([$count:literal])? $msb:literal, $lsb:literal; $($rest:tt)*) => {
+        $crate::volatile::register_getter!([$(#[$attr])*] [$($get)?] $int => $int, [$($count)?] $msb, $lsb);
+        $crate::volatile::register_setter!([$(#[$attr])*] [$($set)?] $int => $int, [$($count)?] $msb, $lsb);
+        $crate::volatile::register_fields!($int; $($rest)*);
+    },
+    ($int:ty; $(#[$attr:meta])* $($get:ident)? $(_)?, $($set:ident)? $(_)?:
+     $([$count:literal])? $msb:literal, $lsb:literal => $value:ty; $($rest:tt)*) => {
+        $crate::volatile::register_getter!([$(#[$attr])*] [$($get)?] $int => $value, [$($count)?] $msb, $lsb);
+        $crate::volatile::register_setter!([$(#[$attr])*] [$($set)?] $int => $value, [$($count)?] $msb, $lsb);
+        $crate::volatile::register_fields!($int; $($rest)*);
+    },
+}
+
+#[doc(hidden)]
+pub macro register_getter {
+    ([$(#[$attr:meta])*] [] $int:ty => $value:ty, [$($count:literal)?] $msb:literal, $lsb:literal) => {},
+    ([$(#[$attr:meta])*] [$get:ident] $int:ty => $value:ty, [] $msb:literal, $lsb:literal) => {
+        $(#[$attr])*
+        #[inline(always)]
+        #[allow(dead_code)]
+        pub fn $get(&self) -> <$value as $crate::volatile::FieldValue<$int>>::Read {
+            let bits = $crate::volatile::Bits::field(
+                $crate::volatile::Register::bits(*self), $lsb, $msb - $lsb + 1);
+            <$value as $crate::volatile::FieldValue<$int>>::from_bits(bits)
+        }
+    },
+    ([$(#[$attr:meta])*] [$get:ident] $int:ty => $value:ty, [$count:literal] $msb:literal, $lsb:literal) => {
+        $(#[$attr])*
+        ///
+        /// # Panics
+        ///
+        /// Panics if `index` is out of bounds.
+        #[inline(always)]
+        #[allow(dead_code)]
+        pub fn $get(&self, index: usize) -> <$value as $crate::volatile::FieldValue<$int>>::Read {
+            assert!(index < $count, "field index {} out of bounds", index);
+            let width = $msb - $lsb + 1;
+            let bits = $crate::volatile::Bits::field(
+                $crate::volatile::Register::bits(*self), $lsb + index * width, width);
+            <$value as $crate::volatile::FieldValue<$int>>::from_bits(bits)
+        }
+    },
+}
+
+#[doc(hidden)]
+pub macro register_setter {
+    ([$(#[$attr:meta])*] [] $int:ty => $value:ty, [$($count:literal)?] $msb:literal, $lsb:literal) => {},
+    ([$(#[$attr:meta])*] [$set:ident] $int:ty => $value:ty, [] $msb:literal, $lsb:literal) => {
+        $(#[$attr])*
+        #[inline(always)]
+        #[allow(dead_code)]
+        pub fn $set(self, value: $value) -> Self {
+            let bits = <$value as $crate::volatile::FieldValue<$int>>::into_bits(value);
+            let bits = $crate::volatile::Bits::with_field(
+                $crate::volatile::Register::bits(self), $lsb, $msb - $lsb + 1, bits);
+            <Self as $crate::volatile::Register>::from_bits(bits)
+        }
+    },
+    ([$(#[$attr:meta])*] [$set:ident] $int:ty => $value:ty, [$count:literal] $msb:literal, $lsb:literal) => {
+        $(#[$attr])*
+        ///
+        /// # Panics
+        ///
+        /// Panics if `index` is out of bounds.
+        #[inline(always)]
+        #[allow(dead_code)]
+        pub fn $set(self, index: usize, value: $value) -> Self {
+            assert!(index < $count, "field index {} out of bounds", index);
+            let width = $msb - $lsb + 1;
+            let bits = <$value as $crate::volatile::FieldValue<$int>>::into_bits(value);
+            let bits = $crate::volatile::Bits::with_field(
+                $crate::volatile::Register::bits(self), $lsb + index * width, width, bits);
+            <Self as $crate::volatile::Register>::from_bits(bits)
+        }
+    },
+}
+
+/// Declares a fieldless enum of the values a register field takes, encoded
+/// in the register's integer. Reading the field returns `None` for encodings
+/// with no variant.
+///
+/// ```rust,ignore
+/// field_enum! {
+///     /// The number of data bits of the mini UART.
+///     enum DataSize: u8 {
+///         SevenBit = 0b00,
+///         EightBit = 0b11,
+///     }
+/// }
+/// ```
+pub macro field_enum(
+    $(#[$attr:meta])* $vis:vis enum $name:ident: $int:ty {
+        $($(#[$variant_attr:meta])* $variant:ident = $bits:expr),* $(,)?
+    }
+) {
+    $(#[$attr])*
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    $vis enum $name {
+        $($(#[$variant_attr])* $variant = $bits),*
+    }
+
+    impl $crate::volatile::FieldValue<$int> for $name {
+        type Read = Option<$name>;
+
+        fn from_bits(bits: $int) -> Option<$name> {
+            $(if bits == $bits {
+                return Some($name::$variant);
+            })*
+            None
+        }
+
+        #[inline(always)]
+        fn into_bits(self) -> $int {
+            self as $int
+        }
+    }
+}
+
+#[cfg(test)]
+mod register_test {
+    use super::*;
+    use crate::volatile::prelude::*;
+    use crate::volatile::Volatile;
+
+    field_enum! {
+        enum Mode: u32 {
+            Off = 0b00,
+            Slow = 0b01,
+            Fast = 0b11,
+        }
+    }
+
+    register! {
+        struct Control(u32) {
+            enable, set_enable: 0, 0 => bool;
+            mode, set_mode: 2, 1 => Mode;
+            divisor, set_divisor: 15, 8;
+            status, _: 16, 16 => bool;
+            _, set_reset: 31, 31 => bool;
+        }
+    }
+
+    register! {
+        struct Select(u32) {
+            function, set_function: [10] 2, 0;
+        }
+    }
+
+    #[test]
+    fn test_fields() {
+        let control = Control::from_bits(0x1_2a05);
+        assert!(control.enable());
+        assert_eq!(control.mode(), None);
+        assert_eq!(control.divisor(), 0x2a);
+        assert!(control.status());
+
+        let control = Control::default()
+            .set_enable(true)
+            .set_mode(Mode::Fast)
+            .set_divisor(0xff)
+            .set_reset(true);
+        assert_eq!(control.bits(), 0x8000_ff07);
+        assert_eq!(control.mode(), Some(Mode::Fast));
+        assert_eq!(control.set_mode(Mode::Slow).bits(), 0x8000_ff03);
+        assert_eq!(control.set_divisor(0).set_enable(false).bits(), 0x8000_0006);
+        assert_eq!(format!("{:?}", control), "Control(0x8000ff07)");
+    }
+
+    #[test]
+    fn test_indexed_fields() {
+        let select = Select::from_bits(0xffff_ffff).set_function(4, 0b010).set_function(9, 0);
+        assert_eq!(select.bits(), 0xc7ff_afff);
+        assert_eq!(select.function(4), 0b010);
+        assert_eq!(select.function(9), 0);
+        assert_eq!(select.function(0), 0b111);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn test_index_out_of_bounds() {
+        Select::default().function(10);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "wider than 3 bits")]
+    fn test_value_too_wide() {
+        Select::default().set_function(0, 0b1000);
+    }
+
+    #[test]
+    fn test_modify() {
+        let mut bits = 0x1_0000u32;
+        let register = unsafe { &mut *(&mut bits as *mut u32 as *mut Volatile<Control>) };
+        register.modify(|control| control.set_enable(true).set_divisor(3));
+        assert_eq!(register.read().bits(), 0x1_0301);
+        assert!(register.read().status());
+    }
+}
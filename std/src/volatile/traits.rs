@@ -47,6 +47,14 @@ pub trait Writeable<T> {
     fn write(&mut self, val: T) {
         unsafe { ::core::ptr::write_volatile(self.inner(), val) }
     }
+
+    /// Reads the value pointed to by `self`, and writes back the value `f`
+    /// returns for it. This is equivalent to `self.write(f(self.read()))`.
+    #[inline(always)]
+    fn modify<F: FnOnce(T) -> T>(&mut self, f: F) where Self: Readable<T> {
+        let init_val = self.read();
+        self.write(f(init_val));
+    }
 }
 
 /// Trait implemented by **readable _and_ writeable** volatile wrappers.
@@ -0,0 +1,169 @@
+//! An in-memory channel simulating a noisy serial line, for tests of the
+//! protocol's error recovery and of its users. Available with the `host`
+//! feature.
+
+use core::time::Duration;
+use host::sync::mpsc::{channel as mpsc_channel, Receiver, RecvTimeoutError, Sender};
+use host::thread;
+
+use crate::io::{Error, ErrorKind, Read, ReadTimeout, Write};
+use super::{progress, Xmodem, XmodemConfig};
+
+/// How long `Transmission::result` waits for the sender.
+const WATCHDOG: Duration = Duration::from_secs(30);
+
+/// Probabilities, in parts per million, of each fault hitting a byte written
+/// to a channel.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Faults {
+    /// The byte is lost.
+    pub drop: u32,
+    /// A random bit of the byte is flipped.
+    pub flip: u32,
+    /// The byte is received twice.
+    pub duplicate: u32,
+    /// The byte is held back for `delay_ms` milliseconds.
+    pub delay: u32,
+    /// Duration in milliseconds of a delay.
+    pub delay_ms: u64,
+}
+
+/// A xorshift pseudo-random generator, so that a seed always yields the same
+/// schedule of faults.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    /// Returns a generator seeded with `seed`.
+    pub fn new(seed: u64) -> Rng {
+        // Zero is a fixed point of xorshift.
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    /// Returns the next pseudo-random number.
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a number in `0..n`.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// Returns `true` with a probability of `ppm` parts per million.
+    pub fn chance(&mut self, ppm: u32) -> bool {
+        self.below(1_000_000) < ppm as u64
+    }
+}
+
+/// One end of a channel. Bytes written to it are hit by faults on their way
+/// to the other end. Reads time out once a read timeout is set, and the
+/// stream ends once the other end is dropped.
+pub struct Channel {
+    tx: Sender<u8>,
+    rx: Receiver<u8>,
+    timeout: Option<Duration>,
+    faults: Faults,
+    rng: Rng,
+}
+
+/// Returns the two ends of a channel. Bytes written to either end are hit by
+/// `faults`, on a schedule determined by `seed`.
+pub fn channel(seed: u64, faults: Faults) -> (Channel, Channel) {
+    let ((tx1, rx1), (tx2, rx2)) = (mpsc_channel(), mpsc_channel());
+    let end = |tx, rx, seed| Channel { tx, rx, timeout: None, faults, rng: Rng::new(seed) };
+    (end(tx1, rx2, seed), end(tx2, rx1, !seed))
+}
+
+impl Read for Channel {
+    /// Reads a single byte.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let byte = match self.timeout {
+            Some(timeout) => self.rx.recv_timeout(timeout),
+            None => self.rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match byte {
+            Ok(byte) => buf[0] = byte,
+            Err(RecvTimeoutError::Timeout) => return Err(ErrorKind::TimedOut.into()),
+            Err(RecvTimeoutError::Disconnected) => return Ok(0),
+        }
+        Ok(1)
+    }
+}
+
+impl Channel {
+    /// Sends `byte` to the other end, unless it is dropped.
+    fn send(&mut self, byte: u8) -> Result<(), Error> {
+        let faults = self.faults;
+        if self.rng.chance(faults.drop) {
+            return Ok(());
+        }
+        if self.rng.chance(faults.delay) {
+            thread::sleep(Duration::from_millis(faults.delay_ms));
+        }
+
+        let mut sent = byte;
+        if self.rng.chance(faults.flip) {
+            sent ^= 1 << self.rng.below(8);
+        }
+        self.tx.send(sent).map_err(|_| Error::from(ErrorKind::BrokenPipe))?;
+        if self.rng.chance(faults.duplicate) {
+            self.tx.send(sent).map_err(|_| Error::from(ErrorKind::BrokenPipe))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Write for Channel {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        for byte in buf {
+            self.send(*byte)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl ReadTimeout for Channel {
+    fn set_read_timeout(&mut self, milliseconds: u32) -> Result<(), Error> {
+        self.timeout = Some(Duration::from_millis(milliseconds as u64));
+        Ok(())
+    }
+}
+
+/// A transmission running on a thread of its own, started by `transmit`.
+pub struct Transmission {
+    result: Receiver<Result<usize, Error>>,
+}
+
+impl Transmission {
+    /// Waits for the transmission to end and returns the sender's result.
+    /// Panics if the sender hangs.
+    pub fn result(self) -> Result<usize, Error> {
+        self.result.recv_timeout(WATCHDOG).expect("sender hangs")
+    }
+}
+
+/// Sends `input` over `port` with `Xmodem::transmit_from`, with the retry and
+/// timeout policy `config`, from a new thread.
+pub fn transmit<I>(port: Channel, config: XmodemConfig, input: I) -> Transmission
+    where I: AsRef<[u8]> + Send + 'static
+{
+    let (done, result) = mpsc_channel();
+    thread::spawn(move || {
+        let result = Xmodem::new_with_config(port, config, progress::noop)
+            .and_then(|mut sender| sender.transmit_from(input.as_ref()));
+        done.send(result).expect("send result");
+    });
+    Transmission { result }
+}
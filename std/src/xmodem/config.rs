@@ -0,0 +1,43 @@
+/// Retry and timeout policy of an `Xmodem` transfer.
+#[derive(Debug)]
+pub struct XmodemConfig {
+    /// Number of times a packet is sent or requested again after it was
+    /// rejected, corrupted or timed out.
+    pub packet_retries: usize,
+    /// Number of `C` requests, and then of `NAK` requests, the receiver sends
+    /// while the sender doesn't answer, after as many `G` requests in
+    /// streaming mode. The sender waits for twice as many read timeouts for a
+    /// request.
+    pub handshake_retries: usize,
+    /// Whether to stream packets without acknowledging each of them
+    /// (XMODEM-G), for error-free links. Streaming is only used if both the
+    /// receiver and the sender set it. Any error cancels a streamed transfer.
+    pub streaming: bool,
+    /// Maximum time in milliseconds to wait for each byte. Applied to the
+    /// inner stream by `Xmodem::new_with_config()`.
+    pub byte_timeout: u32,
+    /// Maximum duration in milliseconds of the whole transfer, counted from
+    /// the first request, and the clock measuring it. The clock returns the
+    /// current time in milliseconds.
+    pub deadline: Option<(u64, fn() -> u64)>,
+}
+
+impl Clone for XmodemConfig {
+    fn clone(&self) -> XmodemConfig {
+        *self
+    }
+}
+
+impl Copy for XmodemConfig {}
+
+impl Default for XmodemConfig {
+    fn default() -> XmodemConfig {
+        XmodemConfig {
+            packet_retries: 10,
+            handshake_retries: 3,
+            streaming: false,
+            byte_timeout: 1000,
+            deadline: None,
+        }
+    }
+}
@@ -0,0 +1,20 @@
+/// Updates the running CRC-16/XMODEM value `crc` with `byte`.
+///
+/// The polynomial is `0x1021` with an initial value of `0`, no reflection and
+/// no final XOR, as used by the XMODEM-CRC packet trailer.
+pub fn update(crc: u16, byte: u8) -> u16 {
+    let mut crc = crc ^ ((byte as u16) << 8);
+    for _ in 0..8 {
+        if crc & 0x8000 != 0 {
+            crc = (crc << 1) ^ 0x1021;
+        } else {
+            crc <<= 1;
+        }
+    }
+    crc
+}
+
+/// Returns the CRC-16/XMODEM value of `data`.
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, byte| update(crc, *byte))
+}
@@ -0,0 +1,173 @@
+use core::{cmp, fmt, str};
+use crate::io::{self, Error, ErrorKind};
+use super::PACKET_SIZE;
+
+/// Maximum length in bytes of a file name in a YMODEM header: the name and
+/// its NUL fill a 128-byte block 0, leaving no room for the other fields.
+pub const MAX_NAME_LEN: usize = PACKET_SIZE - 1;
+
+/// The file information carried by a YMODEM header (block 0): the file name,
+/// the exact file length in bytes and the modification time in seconds since
+/// the Unix epoch.
+///
+/// A header is encoded as the NUL-terminated name followed by the decimal
+/// length and the octal modification time, separated by a space. Both fields
+/// are optional.
+#[derive(Copy, Clone)]
+pub struct FileHeader {
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    size: Option<usize>,
+    mtime: Option<u64>,
+}
+
+impl FileHeader {
+    /// Returns a new header for the file `name` of `size` bytes, last
+    /// modified at `mtime`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if `name` is empty, longer
+    /// than `MAX_NAME_LEN` bytes or contains a NUL byte, or if the encoded
+    /// header doesn't fit in a 128-byte block.
+    pub fn new(name: &str, size: Option<usize>, mtime: Option<u64>) -> io::Result<FileHeader> {
+        let bytes = name.as_bytes();
+        if bytes.is_empty() || bytes.len() > MAX_NAME_LEN || bytes.contains(&0) {
+            return Err(Error::new(ErrorKind::InvalidInput, "invalid file name"));
+        }
+
+        let mut header = FileHeader { name: [0; MAX_NAME_LEN], name_len: bytes.len(), size, mtime };
+        header.name[..bytes.len()].copy_from_slice(bytes);
+        if header.encoded_len() > PACKET_SIZE {
+            return Err(Error::new(ErrorKind::InvalidInput, "file header longer than a block"));
+        }
+        Ok(header)
+    }
+
+    /// Returns the file name.
+    pub fn name(&self) -> &str {
+        str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+
+    /// Returns the exact file length in bytes, if known.
+    pub fn size(&self) -> Option<usize> {
+        self.size
+    }
+
+    /// Returns the modification time in seconds since the Unix epoch, if
+    /// known.
+    pub fn mtime(&self) -> Option<u64> {
+        self.mtime
+    }
+
+    /// Returns the length of the encoded header, without trailing NULs.
+    fn encoded_len(&self) -> usize {
+        let mut digits = [0u8; 22];
+        let mut len = self.name_len + 1;
+        if let Some(size) = self.size {
+            len += write_number(&mut digits, size as u64, 10);
+            if let Some(mtime) = self.mtime {
+                len += 1 + write_number(&mut digits, mtime, 8);
+            }
+        }
+        len
+    }
+
+    /// Encodes `self` into `block`, which is zeroed first. `block` must hold
+    /// at least 128 bytes.
+    pub(super) fn encode(&self, block: &mut [u8]) {
+        block.iter_mut().for_each(|b| *b = 0);
+        block[..self.name_len].copy_from_slice(&self.name[..self.name_len]);
+
+        let mut i = self.name_len + 1;
+        if let Some(size) = self.size {
+            i += write_number(&mut block[i..], size as u64, 10);
+            if let Some(mtime) = self.mtime {
+                block[i] = b' ';
+                write_number(&mut block[i + 1..], mtime, 8);
+            }
+        }
+    }
+
+    /// Parses a header from `block`. Returns `None` if the file name is
+    /// empty, which marks the end of a batch. A name longer than
+    /// `MAX_NAME_LEN` bytes, which only fits in a 1K block, is truncated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidData` if the name isn't terminated or
+    /// isn't valid UTF-8, or if a field isn't a number.
+    pub(super) fn parse(block: &[u8]) -> io::Result<Option<FileHeader>> {
+        let name_len = match block.iter().position(|b| *b == 0) {
+            Some(0) => return Ok(None),
+            Some(n) => n,
+            None => return Err(Error::new(ErrorKind::InvalidData, "unterminated file name")),
+        };
+        let invalid_name = Error::new(ErrorKind::InvalidData, "invalid file name");
+        let name = str::from_utf8(&block[..name_len])
+            .map_err(|_| invalid_name)?;
+        let mut len = cmp::min(name.len(), MAX_NAME_LEN);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut header = FileHeader::new(&name[..len], None, None)
+            .map_err(|_| invalid_name)?;
+
+        let info = &block[(name_len + 1)..];
+        let info = &info[..info.iter().position(|b| *b == 0).unwrap_or(info.len())];
+        let mut fields = info.split(|b| *b == b' ').filter(|f| !f.is_empty());
+        if let Some(size) = fields.next() {
+            header.size = Some(parse_number(size, 10)? as usize);
+        }
+        if let Some(mtime) = fields.next() {
+            header.mtime = Some(parse_number(mtime, 8)?);
+        }
+
+        Ok(Some(header))
+    }
+}
+
+impl fmt::Debug for FileHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FileHeader")
+            .field("name", &self.name())
+            .field("size", &self.size)
+            .field("mtime", &self.mtime)
+            .finish()
+    }
+}
+
+/// Writes `n` in base `radix` into `buf` and returns the number of digits.
+fn write_number(buf: &mut [u8], mut n: u64, radix: u64) -> usize {
+    let mut digits = [0u8; 22];
+    let mut len = 0;
+    loop {
+        digits[len] = b'0' + (n % radix) as u8;
+        len += 1;
+        n /= radix;
+        if n == 0 {
+            break;
+        }
+    }
+
+    for i in 0..len {
+        buf[i] = digits[len - 1 - i];
+    }
+    len
+}
+
+/// Parses `digits` as a number in base `radix`.
+fn parse_number(digits: &[u8], radix: u64) -> io::Result<u64> {
+    let invalid_number = Error::new(ErrorKind::InvalidData, "invalid number in file header");
+    let mut n: u64 = 0;
+    for digit in digits {
+        let value = (*digit as u64).wrapping_sub(b'0' as u64);
+        if value >= radix {
+            return Err(invalid_number);
+        }
+        n = n.checked_mul(radix)
+            .and_then(|n| n.checked_add(value))
+            .ok_or(invalid_number)?;
+    }
+    Ok(n)
+}
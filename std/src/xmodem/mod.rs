@@ -1,24 +1,59 @@
-mod read_ext;
 mod progress;
+mod crc;
+mod header;
+mod config;
+#[cfg(any(test, feature = "host"))]
+pub mod channel;
 
 use crate::io::Read;
 use crate::io::Write;
-use crate::io::ErrorKind;
-use read_ext::ReadExt;
-use progress::*;
+use crate::io::ReadTimeout;
+use crate::io::{self, Error, ErrorKind};
+
+pub use header::{FileHeader, MAX_NAME_LEN};
+pub use progress::{Progress, ProgressFn, RetryReason};
+pub use config::XmodemConfig;
 
 const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
 const EOT: u8 = 0x04;
 const ACK: u8 = 0x06;
 const NAK: u8 = 0x15;
 const CAN: u8 = 0x18;
+const CRC: u8 = 0x43;
+const STREAMING: u8 = 0x47;
+
+/// Payload size of a `SOH` packet.
+const PACKET_SIZE: usize = 128;
+/// Payload size of a `STX` (XMODEM-1K) packet.
+const PACKET_1K_SIZE: usize = 1024;
 
 
-pub struct Xmodem<R> {
+/// Implementation of the XMODEM protocol. Progress is reported to a callback
+/// of type `F`.
+pub struct Xmodem<R, F = ProgressFn> {
     packet: u8,     // package ID. 0~255. Roll back to 0: 0=>255=>0
     inner: R,       // receiver or transmiter
     started: bool,
-    progress: ProgressFn,
+    crc: bool,      // CRC-16 or 8-bit checksum trailer
+    streaming: bool,    // packets aren't acknowledged (XMODEM-G)
+    config: XmodemConfig,
+    start_time: Option<u64>,    // clock reading when the handshake started
+    offset: usize,              // bytes of the current file transferred
+    total: Option<usize>,       // size of the current file, if known
+    set_read_timeout: Option<fn(&mut R, u32) -> io::Result<()>>,   // of `inner`, if it has one
+    plain: bool,                // packet 1 of a plain XMODEM transfer may replace block 0
+    progress: F,
+}
+
+/// The first block of a reception, read by `Xmodem::read_header_or_packet`.
+#[derive(Debug)]
+pub enum Block {
+    /// A YMODEM header (block 0), or `None` if it is empty and closes the
+    /// batch.
+    Header(Option<FileHeader>),
+    /// The first packet of a plain XMODEM transfer, of the given size.
+    Packet(usize),
 }
 
 impl Xmodem<()> {
@@ -26,53 +61,46 @@ impl Xmodem<()> {
     /// If transmit successfully, return the byte number.
     /// Else, return Err(())
     #[inline]
-    pub fn transmit<R, W>(ds: R, port: W) -> Result<usize, ErrorKind> 
+    pub fn transmit<R, W>(ds: R, port: W) -> io::Result<usize> 
         where R: Read,
               W: Read + Write
     {
         Xmodem::transmit_with_progress(ds, port, progress::noop)
     }
 
+    /// Read data from *ds* and send the data by *port*, calling `f` to report
+    /// progress. See the [`Progress`] enum for more information.
+    ///
+    /// When the receiver requests CRC mode, data is sent in 1024-byte `STX`
+    /// packets. A tail that fits in fewer 128-byte packets is sent in `SOH`
+    /// packets to reduce padding.
     #[inline]
-    pub fn transmit_with_progress<R, W>(mut ds: R, port: W, f: ProgressFn) -> Result<usize, ErrorKind> 
+    pub fn transmit_with_progress<R, W, F>(ds: R, port: W, f: F) -> io::Result<usize> 
         where R: Read,
-              W: Read + Write
+              W: Read + Write,
+              F: FnMut(Progress)
     {
-        let mut transmitter = Xmodem::new_with_progress(port, f);
-        let mut packet = [0u8; 128];
-        let mut written = 0;
-        'next_packet: loop {
-            let n = ds.read_max(&mut packet)?;
-            packet[n..].iter_mut().for_each(|b| *b = 0);
-
-            if n == 0 {
-                transmitter.write_packet(&[])?;
-                return Ok(written);
-            }
-
-            for _ in 0..10 {
-                match transmitter.write_packet(&packet) {
-                    Err(e) => {
-                        match e {
-                            ErrorKind::Interrupted => continue,
-                            _ => return Err(e),
-                        }
-                    },
-                    Ok(_) => {
-                        written += n;
-                        continue 'next_packet;
-                    }
-                }
-            }
+        Xmodem::new_with_progress(port, f).transmit_from(ds)
+    }
 
-            return Err(ErrorKind::BrokenPipe);
-        }
+    /// Send `files` by *port* as a YMODEM batch. Each file is announced by its
+    /// header before its data is sent. The batch is closed with an empty
+    /// header once every file is sent.
+    ///
+    /// Returns the total number of bytes sent, excluding padding zeroes.
+    pub fn transmit_batch<I, R, W, F>(files: I, port: W, f: F) -> io::Result<usize>
+        where I: IntoIterator<Item = (FileHeader, R)>,
+              R: Read,
+              W: Read + Write,
+              F: FnMut(Progress)
+    {
+        Xmodem::new_with_progress(port, f).transmit_files(files)
     }
 
     /// Receives `data` from `from` using the XMODEM protocol and writes it into
     /// `into`. Returns the number of bytes read from `from`, a multiple of 128.
     #[inline]
-    pub fn receive<R, W>(port: R, into: W) -> Result<usize, ErrorKind>
+    pub fn receive<R, W>(port: R, into: W) -> io::Result<usize>
        where R: Read + Write,
              W: Write
     {
@@ -84,34 +112,12 @@ impl Xmodem<()> {
     ///
     /// The function `f` is used as a callback to indicate progress throughout
     /// the reception. See the [`Progress`] enum for more information.
-    pub fn receive_with_progress<R, W>(port: R, mut into: W, f: ProgressFn) -> Result<usize, ErrorKind>
+    pub fn receive_with_progress<R, W, F>(port: R, into: W, f: F) -> io::Result<usize>
        where R: Read + Write, 
-             W: Write
+             W: Write,
+             F: FnMut(Progress)
     {
-        let mut receiver = Xmodem::new_with_progress(port, f);
-        let mut packet = [0u8; 128];
-        let mut received = 0;
-        'next_packet: loop {
-            for _ in 0..10 {
-                match receiver.read_packet(&mut packet) {
-                    Err(e) => {
-                        match e {
-                            ErrorKind::Interrupted => continue,
-                            _ => return Err(e),
-                        }
-                    },
-                    Ok(0) => break 'next_packet,
-                    Ok(n) => {
-                        received += n;
-                        into.write(&packet)?;
-                        continue 'next_packet;
-                    }
-                }
-            }
-
-            return Err(ErrorKind::BrokenPipe);
-        }
-        Ok(received)
+        Xmodem::new_with_progress(port, f).receive_into(into, None)
     }
 }
 
@@ -121,92 +127,309 @@ impl<T:Read + Write> Xmodem<T> {
     /// `inner`. The returned instance can be used for both receiving
     /// (downloading) and sending (uploading).
     pub fn new(inner: T) -> Self {
-        Xmodem { packet: 1, started: false, inner, progress: progress::noop}
+        Xmodem::new_with_progress(inner, progress::noop)
     }
+}
 
+impl<T: Read + Write, F: FnMut(Progress)> Xmodem<T, F> {
     /// Returns a new `Xmodem` instance with the internal reader/writer set to
     /// `inner`. The returned instance can be used for both receiving
-    /// (downloading) and sending (uploading). The function `f` is used as a
-    /// callback to indicate progress throughout the transfer. See the
-    /// [`Progress`] enum for more information.
-    pub fn new_with_progress(inner: T, f: ProgressFn) -> Self {
-        Xmodem { packet: 1, started: false, inner, progress: f }
+    /// (downloading) and sending (uploading). The function or closure `f` is
+    /// used as a callback to indicate progress throughout the transfer. See
+    /// the [`Progress`] enum for more information.
+    pub fn new_with_progress(inner: T, f: F) -> Self {
+        Xmodem {
+            packet: 1,
+            started: false,
+            crc: false,
+            streaming: false,
+            config: XmodemConfig::default(),
+            start_time: None,
+            offset: 0,
+            total: None,
+            set_read_timeout: None,
+            plain: false,
+            inner,
+            progress: f,
+        }
+    }
+
+    /// Returns a new `Xmodem` instance with the internal reader/writer set to
+    /// `inner` and the retry and timeout policy set to `config`. The read
+    /// timeout of `inner` is set to `config.byte_timeout`. The function `f` is
+    /// used as a callback to indicate progress throughout the transfer.
+    pub fn new_with_config(mut inner: T, config: XmodemConfig, f: F) -> io::Result<Self>
+        where T: ReadTimeout
+    {
+        inner.set_read_timeout(config.byte_timeout)?;
+        let mut xmodem = Xmodem::new_with_progress(inner, f);
+        xmodem.config = config;
+        xmodem.set_read_timeout = Some(T::set_read_timeout as fn(&mut T, u32) -> io::Result<()>);
+        Ok(xmodem)
     }
 
     /// basic data send and receive functions
-    /// Read a byte
-    fn read_byte(&mut self, abort_on_can: bool) -> Result<u8, ErrorKind> {
+    /// Read a byte. If `abort_on_can` is set, two `CAN`s in a row abort the
+    /// transfer with `ConnectionAborted` while a lone `CAN` is discarded as
+    /// line noise.
+    fn read_byte(&mut self, abort_on_can: bool) -> io::Result<u8> {
         let byte = self.inner.read_byte()?;
 
         if abort_on_can && byte == CAN {
-            return Err(ErrorKind::ConnectionAborted);
+            return match self.inner.read_byte()? {
+                CAN => Err(Error::new(ErrorKind::ConnectionAborted, "cancelled by the other side")),
+                byte => Ok(byte),
+            };
         }
 
         Ok(byte)
     }
 
-    /// Send a byte
-    fn write_byte(&mut self, byte: u8) -> Result<u8, ErrorKind> {
-        self.inner.write_byte(byte)
+    /// Send a control byte right away
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.inner.write_byte(byte)?;
+        self.inner.flush()
     }
 
     /// Reads a single byte from the inner I/O stream and compares it to `byte`.
     /// If the bytes match, the byte is returned as an `Ok`. If they differ and
-    /// the read byte is not `CAN`, an error of `InvalidData` with the message
-    /// `expected` is returned. If they differ and the read byte is `CAN`, an
-    /// error of `ConnectionAborted` is returned. In either case, if they bytes
-    /// differ, a `CAN` byte is written out to the inner stream.
+    /// the read byte isn't the first of two `CAN`s, an error of `InvalidData`
+    /// is returned and the transfer is cancelled with two `CAN`s. If the other
+    /// side cancelled the transfer, an error of `ConnectionAborted` is
+    /// returned.
     ///
     /// # Errors
     ///
     /// Returns an error if reading from the inner stream fails, if the read
-    /// byte was not `byte`, if the read byte was `CAN` and `byte` is not `CAN`,
-    /// or if writing the `CAN` byte failed on byte mismatch.
-    fn expect_byte_or_cancel(&mut self, byte: u8) -> Result<u8, ErrorKind> {
-        let byte_read = self.read_byte(false)?;
-        if byte_read==byte {
-            return Ok(byte);
-        }
-        else {
-            self.write_byte(CAN)?;
-            if byte_read==CAN {
-                return Err(ErrorKind::ConnectionAborted);
-            }
-            else{
-                return Err(ErrorKind::InvalidData);
+    /// byte was not `byte`, if two `CAN`s were read and `byte` is not `CAN`,
+    /// or if writing the `CAN` bytes failed on byte mismatch.
+    fn expect_byte_or_cancel(&mut self, byte: u8, expected: &'static str) -> io::Result<u8> {
+        match self.expect_byte(byte, expected) {
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                self.write_cancel()?;
+                Err(e)
             }
+            result => result,
         }
     }
 
     /// Reads a single byte from the inner I/O stream and compares it to `byte`.
-    /// If they differ, an error of `InvalidData` with the message `expected` is
-    /// returned. Otherwise the byte is returned. If `byte` is not `CAN` and the
-    /// read byte is `CAN`, a `ConnectionAborted` error is returned.
+    /// If they differ, an error of `InvalidData` is returned. Otherwise the
+    /// byte is returned. If `byte` is not `CAN` and the read bytes are two
+    /// `CAN`s, a `ConnectionAborted` error is returned.
     ///
     /// # Errors
     ///
     /// Returns an error if reading from the inner stream fails, or if the read
-    /// byte was not `byte`. If the read byte differed and was `CAN`, an error
-    /// of `ConnectionAborted` is returned. Otherwise, the error kind is
-    /// `InvalidData`.
-    fn expect_byte(&mut self, byte: u8) -> Result<u8, ErrorKind> {
-        let byte_read = self.read_byte(false)?;
-        if byte_read==byte {
-            return Ok(byte);
-        }
-        else {
-            if byte_read==CAN {
-                return Err(ErrorKind::ConnectionAborted);
+    /// byte was not `byte`. If the read byte differed and was followed by a
+    /// second `CAN`, an error of `ConnectionAborted` is returned. Otherwise,
+    /// the error kind is `InvalidData`.
+    fn expect_byte(&mut self, byte: u8, expected: &'static str) -> io::Result<u8> {
+        match self.read_byte(false)? {
+            byte_read if byte_read == byte => Ok(byte),
+            CAN if self.read_byte(false)? == CAN => {
+                Err(Error::new(ErrorKind::ConnectionAborted, "cancelled by the other side"))
             }
-            else{
-                return Err(ErrorKind::InvalidData);
+            _ => Err(Error::new(ErrorKind::InvalidData, expected)),
+        }
+    }
+
+    /// Sends two `CAN`s, which abort the transfer on the other side.
+    fn write_cancel(&mut self) -> io::Result<()> {
+        self.inner.write_all(&[CAN, CAN])?;
+        self.inner.flush()
+    }
+
+    /// Cancels the transfer on purpose: sends two `CAN`s, which make the other
+    /// side abort with an error of kind `ConnectionAborted`, then discards
+    /// incoming bytes until the inner stream times out or ends. The
+    /// cancellation is reported with `Progress::Cancelled`.
+    ///
+    /// The next packet starts a new transfer with a handshake.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing the `CAN`s or reading from the inner
+    /// stream fails. Reads are expected to time out eventually, so a read
+    /// timeout should be set on the inner stream.
+    pub fn cancel(&mut self) -> io::Result<()> {
+        self.write_cancel()?;
+        self.started = false;
+        self.offset = 0;
+        self.total = None;
+        (self.progress)(Progress::Cancelled);
+        self.purge()
+    }
+
+    /// Returns `ConnectionAborted` if the receiver has sent two `CAN`s since
+    /// the last packet. Used in streaming mode, where the sender doesn't
+    /// otherwise read until the `EOT`: the bytes already received are read
+    /// without waiting for more, and any other byte is discarded. Does
+    /// nothing if the inner stream has no read timeout to set.
+    fn check_cancelled(&mut self) -> io::Result<()> {
+        let set_read_timeout = match self.set_read_timeout {
+            Some(set_read_timeout) => set_read_timeout,
+            None => return Ok(()),
+        };
+
+        set_read_timeout(&mut self.inner, 0)?;
+        let pending = loop {
+            match self.inner.read_byte() {
+                Ok(CAN) => break Ok(true),
+                Ok(_) => continue,
+                Err(ref e) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::UnexpectedEof => {
+                    break Ok(false);
+                }
+                Err(e) => break Err(e),
             }
+        };
+        set_read_timeout(&mut self.inner, self.config.byte_timeout)?;
+
+        if !pending? {
+            return Ok(());
+        }
+        // The second `CAN` may still be on its way. A lone `CAN` is noise.
+        match self.inner.read_byte() {
+            Ok(CAN) => Err(Error::new(ErrorKind::ConnectionAborted, "cancelled by the other side")),
+            Err(ref e) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::UnexpectedEof => Ok(()),
+            Err(e) => Err(e),
+            Ok(_) => Ok(()),
         }
     }
 
+    /// Starts the transfer clock used for the deadline, if it isn't running.
+    fn start_clock(&mut self) {
+        if self.start_time.is_none() {
+            self.start_time = self.config.deadline.map(|(_, clock)| clock());
+        }
+    }
+
+    /// Returns `TimedOut` if the transfer deadline has passed.
+    fn check_deadline(&self) -> io::Result<()> {
+        match (self.config.deadline, self.start_time) {
+            (Some((limit, clock)), Some(start)) if clock().wrapping_sub(start) > limit => {
+                Err(Error::new(ErrorKind::TimedOut, "transfer deadline passed"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Starts a reception by sending `C` to request CRC mode, preceded by `G`
+    /// to request streaming mode if `config.streaming` is set. If the sender
+    /// doesn't answer `handshake_retries` requests before the inner stream
+    /// times out, falls back to the next mode, down to checksum mode requested
+    /// with `NAK`. Returns the first byte sent by the sender, or `TimedOut` if
+    /// the checksum mode requests time out as well.
+    fn start_receive(&mut self) -> io::Result<u8> {
+        self.start_clock();
+        let requests = [(STREAMING, true, true), (CRC, true, false), (NAK, false, false)];
+        let first = if self.config.streaming { 0 } else { 1 };
+        for &(request, crc, streaming) in requests[first..].iter() {
+            self.crc = crc;
+            self.streaming = streaming;
+            for _ in 0..self.config.handshake_retries {
+                self.write_byte(request)?;
+                match self.read_byte(true) {
+                    Err(ref e) if e.kind() == ErrorKind::TimedOut => continue,
+                    result => {
+                        self.started = true;
+                        return result;
+                    }
+                }
+            }
+        }
+
+        Err(Error::new(ErrorKind::TimedOut, "no response from the sender"))
+    }
+
+    /// Discards incoming bytes until the inner stream times out or ends, but
+    /// no more than two whole packets, to resynchronise with the sender. A packet the
+    /// sender already sent again after stray bytes is discarded as well.
+    fn purge(&mut self) -> io::Result<()> {
+        for _ in 0..2 * (3 + PACKET_1K_SIZE + 2) {
+            match self.read_byte(false) {
+                Err(ref e) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::UnexpectedEof => {
+                    return Ok(());
+                }
+                result => result?,
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Asks the sender to send the current packet again with a `NAK` and
+    /// returns `Interrupted`. The retry is reported with `reason`.
+    ///
+    /// In streaming mode the sender can't go back to a packet, so the transfer
+    /// is cancelled with two `CAN`s instead and `TimedOut` or `InvalidData` is
+    /// returned.
+    fn reject(&mut self, reason: RetryReason) -> io::Result<usize> {
+        if self.streaming {
+            self.write_cancel()?;
+            return match reason {
+                RetryReason::TimedOut => Err(Error::new(ErrorKind::TimedOut, "packet timed out while streaming")),
+                _ => Err(Error::new(ErrorKind::InvalidData, "bad packet while streaming")),
+            };
+        }
+
+        (self.progress)(Progress::Retry { number: self.packet, reason });
+        self.write_byte(NAK)?;
+        Err(Error::new(ErrorKind::Interrupted, "packet rejected"))
+    }
+
+    /// Acknowledges a received packet with an `ACK`, unless in streaming mode.
+    fn acknowledge(&mut self) -> io::Result<()> {
+        if !self.streaming {
+            self.write_byte(ACK)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reports the current packet as transferred and moves on to the next
+    /// one. The offset is advanced by `size` bytes, up to the total.
+    fn advance(&mut self, size: usize) {
+        self.offset += size;
+        if let Some(total) = self.total {
+            self.offset = ::core::cmp::min(self.offset, total);
+        }
+
+        let (number, offset, total) = (self.packet, self.offset, self.total);
+        (self.progress)(Progress::Packet { number, size, offset, total });
+        self.packet = self.packet.wrapping_add(1);
+    }
+
+    /// Reports the end of a transmission and resets the file offset and
+    /// total.
+    fn finish(&mut self) {
+        (self.progress)(Progress::Finished { bytes: self.offset });
+        self.started = false;
+        self.offset = 0;
+        self.total = None;
+    }
+
+    /// Reports a transfer abandoned with `result` as cancelled or timed out.
+    fn report<X>(&mut self, result: io::Result<X>) -> io::Result<X> {
+        match result {
+            Err(ref e) if e.kind() == ErrorKind::ConnectionAborted => (self.progress)(Progress::Cancelled),
+            Err(ref e) if e.kind() == ErrorKind::TimedOut => (self.progress)(Progress::Timeout),
+            _ => {}
+        }
+
+        result
+    }
+
     /// Transmit package
     /// Reads (downloads) a single packet from the inner stream using the XMODEM
-    /// protocol. On success, returns the number of bytes read (always 128).
+    /// protocol. On success, returns the number of bytes read: 128 for a `SOH`
+    /// packet or 1024 for a `STX` packet.
+    ///
+    /// The first packet of a transfer is requested with `C` so that the packet
+    /// is followed by a CRC-16. If the sender doesn't respond, the receiver
+    /// falls back to requesting an 8-bit checksum with `NAK`. If
+    /// `config.streaming` is set, streaming mode is requested with `G` first:
+    /// the sender then sends every packet without waiting for an `ACK`.
     ///
     /// The progress callback is called with `Progress::Start` when reception
     /// for the first packet has started and subsequently with
@@ -218,76 +441,175 @@ impl<T:Read + Write> Xmodem<T> {
     /// point. Also returns an error if the XMODEM protocol indicates an error.
     /// In particular, an `InvalidData` error is returned when:
     ///
-    ///   * The sender's first byte for a packet isn't `EOT` or `SOH`.
     ///   * The sender doesn't send a second `EOT` after the first.
-    ///   * The received packet numbers don't match the expected values.
+    ///   * The received packet numbers match neither the expected packet nor
+    ///     the previous one.
+    ///
+    /// An error of kind `Interrupted` is returned if a packet checksum or CRC
+    /// fails, if the inner stream times out once the transfer started, or if
+    /// the sender's first byte for a packet isn't `EOT`, `SOH` or `STX`. The
+    /// packet is rejected with a `NAK` in these cases, after purging the line
+    /// in the last one. In streaming mode, the transfer is cancelled instead
+    /// and an error of kind `TimedOut` or `InvalidData` is returned.
     ///
-    /// An error of kind `Interrupted` is returned if a packet checksum fails.
+    /// If the sender sends the previous packet again because its `ACK` was
+    /// lost, the packet is acknowledged again and discarded, and an error of
+    /// kind `Interrupted` is returned as well.
     ///
-    /// An error of kind `ConnectionAborted` is returned if a `CAN` byte is
-    /// received when not expected.
+    /// An error of kind `ConnectionAborted` is returned if two `CAN` bytes
+    /// are received when not expected. A lone `CAN` is discarded as noise.
     ///
-    /// An error of kind `UnexpectedEof` is returned if `buf.len() < 128`.
-    pub fn read_packet(&mut self, buf: &mut [u8]) -> Result<usize, ErrorKind> {
+    /// An error of kind `UnexpectedEof` is returned if `buf.len() < 128`, or if
+    /// a `STX` packet is received and `buf.len() < 1024`.
+    pub fn read_packet(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         // check buf
-        if buf.len() < 128 {
-            return Err(ErrorKind::UnexpectedEof);
+        if buf.len() < PACKET_SIZE {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "buffer shorter than a packet"));
         }
+        // 1. wait for SOH, STX or EOT
+        // SOH/STX: OK; EOT: end transimition; Other: cancel
         // Start, only one time.
-        if !self.started{
-            self.started = true;
-            self.write_byte(NAK)?;
+        let read_byte_1 = if !self.started {
             (self.progress)(Progress::Started);
+            self.start_receive()?
+        } else {
+            match self.read_byte(true) {
+                Err(ref e) if e.kind() == ErrorKind::TimedOut => return self.reject(RetryReason::TimedOut),
+                result => result?,
+            }
+        };
+        match self.read_packet_from(read_byte_1, buf) {
+            Err(ref e) if e.kind() == ErrorKind::TimedOut => self.reject(RetryReason::TimedOut),
+            result => result,
         }
-        // 1. wait for SOH or EOT
-        // SOH: OK; EOT: end transimition; Other: cancel
-        let read_byte_1 = self.read_byte(true)?;
+    }
+
+    /// Reads the rest of a packet started by `read_byte_1` into `buf`. See
+    /// `read_packet`.
+    fn read_packet_from(&mut self, read_byte_1: u8, buf: &mut [u8]) -> io::Result<usize> {
         if read_byte_1==EOT{
             self.write_byte(NAK)?;
-            self.expect_byte(EOT)?;
+            self.expect_byte(EOT, "expected a second EOT")?;
             self.write_byte(ACK)?;
-            self.started = false;
+            self.finish();
             return Ok(0);
         }
-        else if read_byte_1!=SOH{
-            return Err(ErrorKind::InvalidData);
-        } // else, recieved SOH, do nothing.
-        // 2. Read packet number
-        self.expect_byte_or_cancel(self.packet)?;
+        let size = match read_byte_1 {
+            SOH => PACKET_SIZE,
+            STX => PACKET_1K_SIZE,
+            _ => {
+                self.purge()?;
+                return self.reject(RetryReason::Garbage);
+            }
+        };
+        if buf.len() < size {
+            self.write_cancel()?;
+            return Err(Error::new(ErrorKind::UnexpectedEof, "buffer shorter than a 1K packet"));
+        }
+        // 2. Read packet number, the previous one if its ACK was lost
+        let number = self.read_byte(false)?;
+        if self.plain && self.packet == 0 && number == 1 {
+            self.packet = 1;
+        }
+        let duplicate = number == self.packet.wrapping_sub(1);
+        if number != self.packet && !duplicate {
+            if number == CAN && self.read_byte(false)? == CAN {
+                return Err(Error::new(ErrorKind::ConnectionAborted, "cancelled by the other side"));
+            }
+            self.write_cancel()?;
+            return Err(Error::new(ErrorKind::InvalidData, "expected the current or previous packet number"));
+        }
         // 3. Read 255-packet number
-        self.expect_byte_or_cancel(!self.packet)?;
-        // 4. Read a packet (128) from the sender
+        self.expect_byte_or_cancel(!number, "expected the packet number complement")?;
+        // 4. Read a packet (128 or 1024) from the sender
         let mut checksum: u8 = 0;
-        let buf_len = buf.len();
-        for byte in buf{
+        let mut crc: u16 = 0;
+        for byte in buf[..size].iter_mut() {
             *byte = self.read_byte(false)?;
             checksum = checksum.wrapping_add(*byte);
+            crc = crc::update(crc, *byte);
         }
-        // 5. Checksum
-        let read_check_sum = self.read_byte(false)?;
+        // 5. Checksum or CRC
+        let valid = if self.crc {
+            let high = self.read_byte(false)? as u16;
+            let low = self.read_byte(false)? as u16;
+            (high << 8 | low) == crc
+        } else {
+            self.read_byte(false)? == checksum
+        };
         // 6. Verify Checksum
-        if read_check_sum!=checksum{
-            self.write_byte(NAK)?;
-            return Err(ErrorKind::Interrupted);
+        if !valid {
+            return self.reject(RetryReason::Corrupted);
         }
-        else {
-            self.write_byte(ACK)?;
-            (self.progress)(Progress::Packet(self.packet));
-            self.packet = self.packet.wrapping_add(1);
-            return Ok(buf_len);
+        if duplicate {
+            (self.progress)(Progress::Retry { number, reason: RetryReason::Duplicate });
+            self.acknowledge()?;
+            return Err(Error::new(ErrorKind::Interrupted, "duplicate packet"));
+        }
+        self.acknowledge()?;
+        self.advance(size);
+        Ok(size)
+    }
+
+    /// Waits for the receiver's `NAK`, `C` or `G` to start a transmission.
+    /// The byte selects the packet trailer: `C` and `G` request a CRC-16
+    /// while `NAK` requests an 8-bit checksum. `G` also requests streaming
+    /// mode; it is ignored unless `config.streaming` is set, and the receiver
+    /// then falls back to `C`. Does nothing if the transmission has started.
+    ///
+    /// The receiver is given twice `handshake_retries` read timeouts to send
+    /// its request, one for each of its `C` and `NAK` requests. Other bytes,
+    /// such as a banner the receiver prints before it starts, are skipped as
+    /// line garbage, up to the size of a 1K packet.
+    fn start_transmit(&mut self) -> io::Result<()> {
+        if self.started {
+            return Ok(());
+        }
+
+        (self.progress)(Progress::Waiting);
+        self.start_clock();
+        let mut request = Err(Error::new(ErrorKind::TimedOut, "no request from the receiver"));
+        let (mut timeouts, mut garbage) = (0, 0);
+        while timeouts < 2 * self.config.handshake_retries {
+            request = self.read_byte(true);
+            match request {
+                Err(ref e) if e.kind() == ErrorKind::TimedOut => timeouts += 1,
+                Ok(NAK) | Ok(CRC) => break,
+                Ok(STREAMING) if self.config.streaming => break,
+                Ok(_) if garbage < PACKET_1K_SIZE => garbage += 1,
+                _ => break,
+            }
         }
+        let (crc, streaming) = match request? {
+            NAK => (false, false),
+            CRC => (true, false),
+            STREAMING if self.config.streaming => (true, true),
+            _ => return Err(Error::new(ErrorKind::InvalidData, "expected NAK, C or G")),
+        };
+        self.crc = crc;
+        self.streaming = streaming;
+        self.started = true;
+        (self.progress)(Progress::Started);
+        Ok(())
     }
 
     /// Sends (uploads) a single packet to the inner stream using the XMODEM
-    /// protocol. If `buf` is empty, end of transmissions is sent. Users of this
-    /// interface should ensure that `write_packet(&[])` is called when data
-    /// transmission is complete. On success, returns the number of bytes
-    /// written.
+    /// protocol. A 128-byte `buf` is sent as a `SOH` packet and a 1024-byte
+    /// `buf` as a `STX` packet. If `buf` is empty, end of transmissions is
+    /// sent. Users of this interface should ensure that `write_packet(&[])` is
+    /// called when data transmission is complete. On success, returns the
+    /// number of bytes written.
+    ///
+    /// The receiver's first byte selects the packet trailer: `C` requests a
+    /// CRC-16 while `NAK` requests an 8-bit checksum. In streaming mode,
+    /// requested with `G`, the packet is sent without waiting for an `ACK`;
+    /// the receiver cancels the transfer if it fails. The `CAN`s are checked
+    /// for before each packet, if `self` was created by `new_with_config`.
     ///
     /// The progress callback is called with `Progress::Waiting` before waiting
-    /// for the receiver's `NAK`, `Progress::Start` when transmission of the
-    /// first packet has started and subsequently with `Progress::Packet` when a
-    /// packet is sent successfully.
+    /// for the receiver's `NAK` or `C`, `Progress::Start` when transmission of
+    /// the first packet has started and subsequently with `Progress::Packet`
+    /// when a packet is sent successfully.
     ///
     /// # Errors
     ///
@@ -295,66 +617,1364 @@ impl<T:Read + Write> Xmodem<T> {
     /// point. Also returns an error if the XMODEM protocol indicates an error.
     /// In particular, an `InvalidData` error is returned when:
     ///
-    ///   * The receiver's first byte isn't a `NAK`.
-    ///   * The receiver doesn't respond with a `NAK` to the first `EOT`.
+    ///   * The receiver's first byte isn't a `NAK` or `C`.
+    ///   * The receiver doesn't respond with a `NAK` or an `ACK` to the first
+    ///     `EOT`.
     ///   * The receiver doesn't respond with an `ACK` to the second `EOT`.
     ///   * The receiver responds to a complete packet with something besides
     ///     `ACK` or `NAK`.
     ///
     /// An error of kind `UnexpectedEof` is returned if `buf.len() < 128 &&
-    /// buf.len() != 0`.
+    /// buf.len() != 0`. An error of kind `InvalidInput` is returned for any
+    /// other length besides 128 and 1024.
     ///
-    /// An error of kind `ConnectionAborted` is returned if a `CAN` byte is
-    /// received when not expected.
+    /// An error of kind `ConnectionAborted` is returned if two `CAN` bytes
+    /// are received when not expected. A lone `CAN` is discarded as noise.
     ///
-    /// An error of kind `Interrupted` is returned if a packet checksum fails.
-    pub fn write_packet(&mut self, buf: &[u8]) -> Result<usize, ErrorKind> {
+    /// An error of kind `Interrupted` is returned if a packet checksum fails or
+    /// if the receiver's reply times out.
+    pub fn write_packet(&mut self, buf: &[u8]) -> io::Result<usize> {
         // Check buf
-        if (buf.len()<128) & !buf.is_empty() {
-            return Err(ErrorKind::UnexpectedEof);
-        }
-        // Wait NAK to start
-        if !self.started{
-            (self.progress)(Progress::Waiting);
-            self.expect_byte(NAK)?;
-            self.started = true;
-            (self.progress)(Progress::Started);
+        let start = match buf.len() {
+            0 | PACKET_SIZE => SOH,
+            PACKET_1K_SIZE => STX,
+            n if n < PACKET_SIZE => {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "packet shorter than 128 bytes"));
+            }
+            _ => return Err(Error::new(ErrorKind::InvalidInput, "packet neither 128 nor 1024 bytes long")),
+        };
+        // Wait NAK or C to start
+        self.start_transmit()?;
+        // Stop streaming as soon as the receiver cancels
+        if self.streaming {
+            self.check_cancelled()?;
         }
         // Check End
         if buf.is_empty(){
             self.write_byte(EOT)?;
-            self.expect_byte(NAK)?;
-            self.write_byte(EOT)?;
-            self.expect_byte(ACK)?;
-            self.started = false;
+            // An EOT sent again after a lost NAK is ACKed right away.
+            match self.read_reply()? {
+                NAK => {
+                    self.write_byte(EOT)?;
+                    if self.read_reply()? != ACK {
+                        return Err(Error::new(ErrorKind::InvalidData, "expected ACK for the second EOT"));
+                    }
+                }
+                ACK => {}
+                _ => return Err(Error::new(ErrorKind::InvalidData, "expected NAK or ACK for EOT")),
+            }
+            self.finish();
             return Ok(0);
         }
-        // 1. send SOH
-        self.write_byte(SOH)?;
-        // 2. send packet number
-        self.write_byte(self.packet)?;
-        // 3. send 255-packet number
-        self.write_byte(!self.packet)?;
+        // 1. send SOH or STX, 2. packet number, 3. 255-packet number
+        self.inner.write_all(&[start, self.packet, !self.packet])?;
         // 4. send packet
-        let mut checksum: u8 = 0;
-        for byte in buf {
-            self.write_byte(*byte)?;
-            checksum = checksum.wrapping_add(*byte);
+        self.inner.write_all(buf)?;
+        // 5. send check sum or CRC
+        if self.crc {
+            let crc = crc::crc16(buf);
+            self.inner.write_all(&[(crc >> 8) as u8, crc as u8])?;
+        } else {
+            let checksum = buf.iter().fold(0, |sum: u8, byte| sum.wrapping_add(*byte));
+            self.inner.write_byte(checksum)?;
         }
-        // 5. send check sum
-        self.write_byte(checksum)?;
-        // 6. read data
-        let read_ack = self.read_byte(true)?;
-        if read_ack==ACK{
-            (self.progress)(Progress::Packet(self.packet));
-            self.packet = self.packet.wrapping_add(1);
+        self.inner.flush()?;
+        // 6. don't wait in streaming mode
+        if self.streaming {
+            self.advance(buf.len());
             return Ok(buf.len());
         }
-        else if read_ack==NAK{
-            return Err(ErrorKind::Interrupted);
+        // 7. read data
+        match self.read_reply()? {
+            ACK => {
+                self.advance(buf.len());
+                Ok(buf.len())
+            }
+            NAK => {
+                (self.progress)(Progress::Retry { number: self.packet, reason: RetryReason::Rejected });
+                Err(Error::new(ErrorKind::Interrupted, "packet rejected"))
+            }
+            _ => Err(Error::new(ErrorKind::InvalidData, "expected ACK or NAK")),
         }
-        else {
-            return Err(ErrorKind::InvalidData);
+    }
+
+    /// Reads the receiver's reply to a packet or an `EOT`. A timeout is
+    /// reported as `Interrupted` so that the packet is sent again.
+    fn read_reply(&mut self) -> io::Result<u8> {
+        match self.read_byte(true) {
+            Err(ref e) if e.kind() == ErrorKind::TimedOut => {
+                (self.progress)(Progress::Retry { number: self.packet, reason: RetryReason::TimedOut });
+                Err(Error::new(ErrorKind::Interrupted, "no reply from the receiver"))
+            }
+            result => result,
+        }
+    }
+
+    /// Sends `buf` with `write_packet`, sending it again while the receiver
+    /// rejects it, up to `packet_retries` more times. Returns `BrokenPipe` if
+    /// every attempt is rejected, or `TimedOut` if the transfer deadline
+    /// passes.
+    fn write_packet_retry(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for _ in 0..=self.config.packet_retries {
+            let deadline = self.check_deadline();
+            self.report(deadline)?;
+            match self.write_packet(buf) {
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                result => return self.report(result),
+            }
+        }
+
+        Err(Error::new(ErrorKind::BrokenPipe, "too many retries"))
+    }
+
+    /// Receives a packet into `buf` with `read_packet`, asking the sender to
+    /// send it again while it is corrupted, up to `packet_retries` more times.
+    /// Returns `BrokenPipe` if every attempt is corrupted, or `TimedOut` if
+    /// the transfer deadline passes.
+    fn read_packet_retry(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        for _ in 0..=self.config.packet_retries {
+            let deadline = self.check_deadline();
+            self.report(deadline)?;
+            match self.read_packet(buf) {
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                result => return self.report(result),
+            }
+        }
+
+        Err(Error::new(ErrorKind::BrokenPipe, "too many retries"))
+    }
+
+    /// Send data from *ds* until it is exhausted and then end the
+    /// transmission. If the length of the data is not a multiple of 128 bytes,
+    /// the data is padded with zeroes.
+    ///
+    /// When the receiver requests CRC mode, data is sent in 1024-byte `STX`
+    /// packets. A tail that fits in fewer 128-byte packets is sent in `SOH`
+    /// packets to reduce padding.
+    ///
+    /// Returns the number of bytes sent, excluding padding zeroes.
+    pub fn transmit_from<R: Read>(&mut self, mut ds: R) -> io::Result<usize> {
+        let start = self.start_transmit();
+        self.report(start)?;
+        let mut packet = [0u8; PACKET_1K_SIZE];
+        let mut buffered = 0;
+        let mut written = 0;
+        loop {
+            buffered += ds.read_max(&mut packet[buffered..])?;
+
+            if buffered == 0 {
+                self.write_packet_retry(&[])?;
+                return Ok(written);
+            }
+
+            // A 1K packet costs no more padding than 8 short packets.
+            let size = if self.crc && buffered > PACKET_1K_SIZE - PACKET_SIZE {
+                PACKET_1K_SIZE
+            } else {
+                PACKET_SIZE
+            };
+            let n = ::core::cmp::min(buffered, size);
+            packet[n..size].iter_mut().for_each(|b| *b = 0);
+
+            self.write_packet_retry(&packet[..size])?;
+            written += n;
+            buffered -= n;
+            for i in 0..buffered {
+                packet[i] = packet[n + i];
+            }
+        }
+    }
+
+    /// Send `files` as a YMODEM batch. Each file is announced by its header
+    /// before its data is sent. The batch is closed with an empty header once
+    /// every file is sent.
+    ///
+    /// Returns the total number of bytes sent, excluding padding zeroes.
+    pub fn transmit_files<I, R>(&mut self, files: I) -> io::Result<usize>
+        where I: IntoIterator<Item = (FileHeader, R)>,
+              R: Read
+    {
+        let mut written = 0;
+        for (header, ds) in files {
+            self.write_header(Some(&header))?;
+            written += self.transmit_from(ds)?;
+        }
+
+        self.write_header(None)?;
+        Ok(written)
+    }
+
+    /// Receives packets until the end of the transmission and writes them
+    /// into `into`. If `size` is known, only the first `size` bytes are
+    /// written and the padding of the last packet is discarded. `size` is
+    /// reported as the total in progress events.
+    ///
+    /// Returns the number of bytes written to `into`, or `UnexpectedEof` if
+    /// the transmission ends before `size` bytes are received.
+    pub fn receive_into<W: Write>(&mut self, mut into: W, size: Option<usize>) -> io::Result<usize> {
+        let mut packet = [0u8; PACKET_1K_SIZE];
+        let mut received = 0;
+        self.total = size;
+        loop {
+            let n = match self.read_packet_retry(&mut packet)? {
+                0 if size.is_some_and(|size| received < size) => {
+                    return Err(Error::new(ErrorKind::UnexpectedEof, "transfer ended before the file size"));
+                }
+                0 => return into.flush().map(|_| received),
+                n => match size {
+                    Some(size) => ::core::cmp::min(n, size - received),
+                    None => n,
+                },
+            };
+
+            into.write_all(&packet[..n])?;
+            received += n;
+        }
+    }
+
+    /// Sends a YMODEM header (block 0) announcing `header`. If `header` is
+    /// `None`, an empty header is sent to close the batch.
+    ///
+    /// After a file header, the receiver requests the file data anew, so the
+    /// next `write_packet` waits for its `NAK` or `C` again. The file size in
+    /// `header` is reported as the total in progress events.
+    pub fn write_header(&mut self, header: Option<&FileHeader>) -> io::Result<()> {
+        let mut block = [0u8; PACKET_SIZE];
+        if let Some(header) = header {
+            header.encode(&mut block);
+        }
+
+        self.packet = 0;
+        self.write_packet_retry(&block)?;
+        self.started = false;
+        self.offset = 0;
+        self.total = header.and_then(|header| header.size());
+        Ok(())
+    }
+
+    /// Receives a YMODEM header (block 0). Returns `None` if the header is
+    /// empty, which closes the batch.
+    ///
+    /// After a file header, the file data is requested anew by the next
+    /// `read_packet`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading the header packet fails. An error of kind
+    /// `InvalidData` is returned if the sender ends a transmission instead of
+    /// sending a header, or if the header is malformed.
+    pub fn read_header(&mut self) -> io::Result<Option<FileHeader>> {
+        let mut block = [0u8; PACKET_1K_SIZE];
+        self.packet = 0;
+        let n = self.read_packet_retry(&mut block)?;
+        if n == 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "expected a header, got EOT"));
+        }
+
+        self.started = false;
+        self.offset = 0;
+        FileHeader::parse(&block[..n])
+    }
+
+    /// Receives either a YMODEM header (block 0), like `read_header`, or the
+    /// first packet of a plain XMODEM transfer into `buf`, like
+    /// `read_packet`. This lets a receiver accept both a batch and a single
+    /// file from senders that don't support YMODEM.
+    ///
+    /// After a header, the file data is requested anew by the next
+    /// `read_packet`. After a packet, the rest of the file follows.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `read_header`. An error of kind `UnexpectedEof`
+    /// is returned if `buf.len() < 128`, or if a `STX` block is received and
+    /// `buf.len() < 1024`.
+    pub fn read_header_or_packet(&mut self, buf: &mut [u8]) -> io::Result<Block> {
+        self.packet = 0;
+        self.plain = true;
+        let result = self.read_packet_retry(buf);
+        self.plain = false;
+        match result? {
+            0 => Err(Error::new(ErrorKind::InvalidData, "expected a header or a packet, got EOT")),
+            n if self.packet == 1 => {
+                self.started = false;
+                self.offset = 0;
+                FileHeader::parse(&buf[..n]).map(Block::Header)
+            }
+            n => Ok(Block::Packet(n)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod xmodem_test {
+    use super::*;
+    use crate::io::host::HostIo;
+    use std::sync::mpsc::{Receiver, Sender, RecvTimeoutError, channel};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::io::{self, Cursor};
+    use std::collections::VecDeque;
+    use std::time::Duration;
+    use std::vec::Vec;
+    use channel::{Faults, Rng};
+
+    /// One end of a pipe. Reads time out once a read timeout is set.
+    struct Pipe(Sender<u8>, Receiver<u8>, Vec<u8>, Option<Duration>);
+
+    fn pipe() -> (Pipe, Pipe) {
+        let ((tx1, rx1), (tx2, rx2)) = (channel(), channel());
+        (Pipe(tx1, rx2, vec![], None), Pipe(tx2, rx1, vec![], None))
+    }
+
+    impl Read for Pipe {
+        /// Reads a single byte. The stream ends once the other end is dropped.
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            let byte = match self.3 {
+                Some(timeout) => self.1.recv_timeout(timeout),
+                None => self.1.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match byte {
+                Ok(byte) => buf[0] = byte,
+                Err(RecvTimeoutError::Timeout) => return Err(ErrorKind::TimedOut.into()),
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            }
+            Ok(1)
+        }
+    }
+
+    impl ReadTimeout for Pipe {
+        fn set_read_timeout(&mut self, milliseconds: u32) -> Result<(), Error> {
+            self.3 = Some(Duration::from_millis(milliseconds as u64));
+            Ok(())
+        }
+    }
+
+    /// A lossy pipe end dropping the written bytes whose indices are in
+    /// `drops`, counted from the first byte written.
+    struct Lossy { pipe: Pipe, written: usize, drops: Vec<usize> }
+
+    impl Read for Lossy {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            self.pipe.read(buf)
+        }
+    }
+
+    impl Write for Lossy {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            for byte in buf {
+                if !self.drops.contains(&self.written) {
+                    self.pipe.write_byte(*byte)?;
+                }
+                self.written += 1;
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    impl ReadTimeout for Lossy {
+        fn set_read_timeout(&mut self, milliseconds: u32) -> Result<(), Error> {
+            self.pipe.set_read_timeout(milliseconds)
+        }
+    }
+
+    /// Returns the default configuration with a `byte_timeout` of
+    /// `milliseconds`.
+    fn config(milliseconds: u32) -> XmodemConfig {
+        XmodemConfig { byte_timeout: milliseconds, ..XmodemConfig::default() }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            for byte in buf {
+                self.2.push(*byte);
+                self.0.send(*byte).map_err(|_| Error::from(ErrorKind::BrokenPipe))?;
+            }
+            Ok(buf.len())
         }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_loop() {
+        let mut input = [0u8; 384];
+        for (i, chunk) in input.chunks_mut(128).enumerate() {
+            chunk.iter_mut().for_each(|b| *b = i as u8);
+        }
+
+        let (tx, rx) = pipe();
+        let tx_thread = std::thread::spawn(move || Xmodem::transmit(HostIo::new(&input[..]), rx));
+        let rx_thread = std::thread::spawn(move || {
+            let mut output = [0u8; 384];
+            Xmodem::receive(tx, HostIo::new(&mut output[..])).map(|_| output)
+        });
+
+        assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 384);
+        let output = rx_thread.join().expect("rx join okay").expect("rx okay");
+        assert_eq!(&input[..], &output[..]);
+    }
+
+    #[test]
+    fn read_byte() {
+        let byte = Xmodem::new(HostIo::new(Cursor::new(vec![CAN])))
+            .read_byte(false)
+            .expect("read a byte");
+
+        assert_eq!(byte, CAN);
+
+        let e = Xmodem::new(HostIo::new(Cursor::new(vec![CAN, CAN])))
+            .read_byte(true)
+            .expect_err("abort on CAN CAN");
+
+        assert_eq!(e.kind(), ErrorKind::ConnectionAborted);
+
+        let byte = Xmodem::new(HostIo::new(Cursor::new(vec![CAN, ACK])))
+            .read_byte(true)
+            .expect("lone CAN is noise");
+
+        assert_eq!(byte, ACK);
+    }
+
+    #[test]
+    fn test_expect_byte() {
+        let mut xmodem = Xmodem::new(HostIo::new(Cursor::new(vec![1, 1])));
+        assert_eq!(xmodem.expect_byte(1, "1").expect("expected"), 1);
+        let e = xmodem.expect_byte(2, "1, please").expect_err("expect the unexpected");
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert_eq!(e.message(), Some("1, please"));
+    }
+
+    #[test]
+    fn test_expect_byte_or_cancel() {
+        let mut buffer = vec![2, 0];
+        let b = Xmodem::new(crate::io::Cursor::new(buffer.as_mut_slice()))
+            .expect_byte_or_cancel(2, "it's a 2")
+            .expect("got a 2");
+
+        assert_eq!(b, 2);
+    }
+
+    #[test]
+    fn test_expect_can() {
+        let mut xmodem = Xmodem::new(HostIo::new(Cursor::new(vec![CAN])));
+        assert_eq!(xmodem.expect_byte(CAN, "hi").expect("CAN"), CAN);
+    }
+
+    #[test]
+    fn test_unexpected_can() {
+        let e = Xmodem::new(HostIo::new(Cursor::new(vec![CAN, CAN])))
+            .expect_byte(SOH, "want SOH")
+            .expect_err("have CAN");
+
+        assert_eq!(e.kind(), ErrorKind::ConnectionAborted);
+    }
+
+    #[test]
+    fn test_cancel_on_unexpected() {
+        let mut buffer = vec![CAN, CAN, 0, 0];
+        let e = Xmodem::new(crate::io::Cursor::new(buffer.as_mut_slice()))
+            .expect_byte_or_cancel(SOH, "want SOH")
+            .expect_err("have CAN");
+
+        assert_eq!(e.kind(), ErrorKind::ConnectionAborted);
+        assert_eq!(&buffer[2..], &[0, 0]);
+
+        let mut buffer = vec![0, 0, 0];
+        let e = Xmodem::new(crate::io::Cursor::new(buffer.as_mut_slice()))
+            .expect_byte_or_cancel(SOH, "want SOH")
+            .expect_err("have 0");
+
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert_eq!(&buffer[1..], &[CAN, CAN]);
+
+        let mut buffer = vec![CAN, 0, 0, 0];
+        let e = Xmodem::new(crate::io::Cursor::new(buffer.as_mut_slice()))
+            .expect_byte_or_cancel(SOH, "want SOH")
+            .expect_err("have a lone CAN");
+
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert_eq!(&buffer[2..], &[CAN, CAN]);
+    }
+
+    #[test]
+    fn test_can_in_packet_and_checksum() {
+        let mut input = [0u8; 256];
+        input[0] = CAN;
+
+        let (tx, rx) = pipe();
+        let tx_thread = std::thread::spawn(move || Xmodem::transmit(HostIo::new(&input[..]), rx));
+        let rx_thread = std::thread::spawn(move || {
+            let mut output = [0u8; 256];
+            Xmodem::receive(tx, HostIo::new(&mut output[..])).map(|_| output)
+        });
+
+        assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 256);
+        let output = rx_thread.join().expect("rx join okay").expect("rx okay");
+        assert_eq!(&input[..], &output[..]);
+    }
+
+    #[test]
+    fn test_transmit_reported_bytes() {
+        let (input, mut output) = ([0u8; 50], [0u8; 128]);
+        let (tx, rx) = pipe();
+        let tx_thread = std::thread::spawn(move || Xmodem::transmit(HostIo::new(&input[..]), rx));
+        let rx_thread = std::thread::spawn(move || Xmodem::receive(tx, HostIo::new(&mut output[..])));
+        assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 50);
+        assert_eq!(rx_thread.join().expect("rx join okay").expect("rx okay"), 128);
+    }
+
+    #[test]
+    fn test_raw_transmission() {
+        let mut input = [0u8; 256];
+        let mut output = [0u8; 256];
+        (0..256usize).into_iter().enumerate().for_each(|(i, b)| input[i] = b as u8);
+
+        let (mut tx, mut rx) = pipe();
+        let tx_thread = std::thread::spawn(move || {
+            Xmodem::transmit(HostIo::new(&input[..]), &mut rx).expect("transmit okay");
+            rx.2
+        });
+
+        let rx_thread = std::thread::spawn(move || {
+            Xmodem::receive(&mut tx, HostIo::new(&mut output[..])).expect("receive okay");
+            tx.2
+        });
+
+        let rx_buf = tx_thread.join().expect("tx join okay");
+        let tx_buf = rx_thread.join().expect("rx join okay");
+
+        // check packet 1
+        let crc = crc::crc16(&input[..128]);
+        assert_eq!(&rx_buf[0..3], &[SOH, 1, 255 - 1]);
+        assert_eq!(&rx_buf[3..(3 + 128)], &input[..128]);
+        assert_eq!(&rx_buf[131..133], &[(crc >> 8) as u8, crc as u8]);
+
+        // check packet 2
+        let crc = crc::crc16(&input[128..]);
+        assert_eq!(&rx_buf[133..136], &[SOH, 2, 255 - 2]);
+        assert_eq!(&rx_buf[136..(136 + 128)], &input[128..]);
+        assert_eq!(&rx_buf[264..266], &[(crc >> 8) as u8, crc as u8]);
+
+        // check EOT
+        assert_eq!(&rx_buf[266..], &[EOT, EOT]);
+
+        // check receiver responses
+        assert_eq!(&tx_buf, &[CRC, ACK, ACK, NAK, ACK]);
+    }
+
+    /// A scripted stream. `None` entries are read as a timeout; everything
+    /// written is recorded.
+    struct Script(VecDeque<Option<u8>>, Vec<u8>);
+
+    impl Script {
+        fn new(entries: VecDeque<Option<u8>>) -> Script {
+            Script(entries, vec![])
+        }
+    }
+
+    impl Read for Script {
+        /// Reads a single entry. The stream ends with the script.
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            match self.0.pop_front() {
+                Some(Some(byte)) => { buf[0] = byte; Ok(1) },
+                Some(None) => Err(ErrorKind::TimedOut.into()),
+                None => Ok(0),
+            }
+        }
+    }
+
+    impl Write for Script {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            self.1.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    impl ReadTimeout for Script {
+        fn set_read_timeout(&mut self, _milliseconds: u32) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc::crc16(b"123456789"), 0x31C3);
+        assert_eq!(crc::crc16(&[]), 0);
+    }
+
+    #[test]
+    fn test_checksum_fallback() {
+        let data = [7u8; 128];
+        let mut script: VecDeque<Option<u8>> = vec![None; XmodemConfig::default().handshake_retries].into();
+        script.extend([SOH, 1, 255 - 1].iter().map(|b| Some(*b)));
+        script.extend(data.iter().map(|b| Some(*b)));
+        script.push_back(Some(data.iter().fold(0, |a: u8, b| a.wrapping_add(*b))));
+        script.extend([EOT, EOT].iter().map(|b| Some(*b)));
+
+        let mut port = Script::new(script);
+        let mut output = [0u8; 128];
+        let n = Xmodem::receive(&mut port, HostIo::new(&mut output[..])).expect("receive okay");
+        assert_eq!(n, 128);
+        assert_eq!(&output[..], &data[..]);
+        assert_eq!(&port.1[..], &[CRC, CRC, CRC, NAK, ACK, NAK, ACK]);
+    }
+
+    #[test]
+    fn test_checksum_transmit() {
+        let data = [7u8; 128];
+        let script = vec![NAK, ACK, NAK, ACK].into_iter().map(Some).collect();
+        let mut port = Script::new(script);
+        let n = Xmodem::transmit(HostIo::new(&data[..]), &mut port).expect("transmit okay");
+        assert_eq!(n, 128);
+        assert_eq!(&port.1[0..3], &[SOH, 1, 255 - 1]);
+        assert_eq!(port.1[131], data.iter().fold(0, |a: u8, b| a.wrapping_add(*b)));
+        assert_eq!(&port.1[132..], &[EOT, EOT]);
+    }
+
+    #[test]
+    fn test_1k_transmission() {
+        let mut input = [0u8; 2348];
+        (0..input.len()).for_each(|i| input[i] = (i % 251) as u8);
+
+        let (mut tx, mut rx) = pipe();
+        let tx_thread = std::thread::spawn(move || {
+            let n = Xmodem::transmit(HostIo::new(&input[..]), &mut rx).expect("transmit okay");
+            (n, rx.2)
+        });
+        let rx_thread = std::thread::spawn(move || {
+            let mut output = vec![0u8; 2432];
+            let n = Xmodem::receive(&mut tx, HostIo::new(&mut output[..])).expect("receive okay");
+            (n, output)
+        });
+
+        let (sent, rx_buf) = tx_thread.join().expect("tx join okay");
+        let (received, output) = rx_thread.join().expect("rx join okay");
+        assert_eq!(sent, 2348);
+        assert_eq!(received, 2 * 1024 + 3 * 128);
+        assert_eq!(&output[..2348], &input[..]);
+        assert!(output[2348..].iter().all(|b| *b == 0));
+
+        // two 1K packets, then the 300 byte tail in three short packets
+        let mut i = 0;
+        for (packet, size) in [(1u8, 1024), (2, 1024), (3, 128), (4, 128), (5, 128)].iter() {
+            let start = if *size == 1024 { STX } else { SOH };
+            assert_eq!(&rx_buf[i..(i + 3)], &[start, *packet, 255 - *packet]);
+            i += 3 + *size + 2;
+        }
+        assert_eq!(&rx_buf[i..], &[EOT, EOT]);
+    }
+
+    #[test]
+    fn test_1k_tail() {
+        let input = [1u8; 1000];
+        let (tx, rx) = pipe();
+        let tx_thread = std::thread::spawn(move || Xmodem::transmit(HostIo::new(&input[..]), rx));
+        let rx_thread = std::thread::spawn(move || {
+            let mut output = [0u8; 1024];
+            Xmodem::receive(tx, HostIo::new(&mut output[..])).map(|n| (n, output))
+        });
+
+        assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 1000);
+        let (n, output) = rx_thread.join().expect("rx join okay").expect("rx okay");
+        assert_eq!(n, 1024);
+        assert_eq!(&output[..1000], &input[..]);
+    }
+
+    #[test]
+    fn test_text_before_handshake() {
+        let data = [7u8; 128];
+        let mut script: VecDeque<Option<u8>> = b"swiftOS boot loader: waiting\r\n".iter().map(|b| Some(*b)).collect();
+        script.extend([STREAMING, CRC, ACK, NAK, ACK].iter().map(|b| Some(*b)));
+        let mut port = Script::new(script);
+        let n = Xmodem::transmit(HostIo::new(&data[..]), &mut port).expect("transmit okay");
+        assert_eq!(n, 128);
+        let packet: Vec<u8> = crc_packet(1, &data).into_iter().map(Option::unwrap).collect();
+        assert_eq!(&port.1[..131 + 2], &packet[..]);
+
+        let script = vec![Some(b'x'); PACKET_1K_SIZE + 1];
+        let e = Xmodem::new(Script::new(script.into()))
+            .write_packet(&data)
+            .expect_err("no request among the garbage");
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_checksum_mode_short_packets() {
+        let data = [7u8; 1024];
+        let mut script = vec![NAK];
+        script.extend(vec![ACK; 8]);
+        script.extend(vec![NAK, ACK]);
+        let mut port = Script::new(script.into_iter().map(Some).collect());
+        let n = Xmodem::transmit(HostIo::new(&data[..]), &mut port).expect("transmit okay");
+        assert_eq!(n, 1024);
+        assert_eq!(port.1.len(), 8 * (3 + 128 + 1) + 2);
+        assert!(port.1.chunks(3 + 128 + 1).take(8).all(|p| p[0] == SOH));
+    }
+
+    #[test]
+    fn test_1k_packet_into_small_buffer() {
+        let mut buffer = vec![0, STX, 1, 254, 0];
+        let mut packet = [0; 128];
+        let e = Xmodem::new(crate::io::Cursor::new(buffer.as_mut_slice()))
+            .read_packet(&mut packet[..])
+            .expect_err("buffer too small");
+
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(&buffer[2..4], &[CAN, CAN]);
+
+        let e = Xmodem::new(HostIo::new(Cursor::new(vec![NAK])))
+            .write_packet(&[0; 512])
+            .expect_err("bad packet size");
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_header_roundtrip() {
+        let header = FileHeader::new("kernel.bin", Some(123456), Some(0o13172461356))
+            .expect("valid header");
+        let mut block = [0xFFu8; 128];
+        header.encode(&mut block);
+        assert_eq!(&block[..30], &b"kernel.bin\x00123456 13172461356\0"[..]);
+        assert!(block[30..].iter().all(|b| *b == 0));
+
+        let parsed = FileHeader::parse(&block).expect("parse okay").expect("a file");
+        assert_eq!(parsed.name(), "kernel.bin");
+        assert_eq!(parsed.size(), Some(123456));
+        assert_eq!(parsed.mtime(), Some(0o13172461356));
+
+        let parsed = FileHeader::parse(b"initrd\0\0").expect("parse okay").expect("a file");
+        assert_eq!(parsed.name(), "initrd");
+        assert_eq!(parsed.size(), None);
+
+        assert!(FileHeader::parse(&[0; 128]).expect("parse okay").is_none());
+        let e = FileHeader::parse(b"x\x0012a4\0").expect_err("bad size");
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        let e = FileHeader::new("", None, None).expect_err("empty name");
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_long_file_name() {
+        let path = "boot/".repeat(20);
+        let header = FileHeader::new(&path, Some(123456), Some(42)).expect("fits in a block");
+        let mut block = [0u8; 128];
+        header.encode(&mut block);
+        let parsed = FileHeader::parse(&block).expect("parse okay").expect("a file");
+        assert_eq!(parsed.name(), path);
+        assert_eq!(parsed.size(), Some(123456));
+        assert_eq!(parsed.mtime(), Some(42));
+
+        let name = "x".repeat(MAX_NAME_LEN);
+        assert_eq!(FileHeader::new(&name, None, None).expect("fits in a block").name(), name);
+        let e = FileHeader::new(&name, Some(1), None).expect_err("size doesn't fit");
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        let e = FileHeader::new(&"x".repeat(MAX_NAME_LEN + 1), None, None).expect_err("name too long");
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+
+        // Only a 1K block holds a longer name, which is truncated.
+        let mut block = [0u8; 1024];
+        block[..200].copy_from_slice("é".repeat(100).as_bytes());
+        block[201..204].copy_from_slice(b"100");
+        let parsed = FileHeader::parse(&block).expect("parse okay").expect("a file");
+        assert_eq!(parsed.name(), "é".repeat(63));
+        assert_eq!(parsed.size(), Some(100));
+    }
+
+    #[test]
+    fn test_ymodem_batch() {
+        let kernel: Vec<u8> = (0..1500).map(|i| (i % 253) as u8).collect();
+        let initrd: Vec<u8> = (0..300).map(|i| (i % 7) as u8 + 1).collect();
+        let files = vec![
+            (FileHeader::new("kernel.bin", Some(kernel.len()), Some(42)).unwrap(), kernel.clone()),
+            (FileHeader::new("initrd", Some(initrd.len()), None).unwrap(), initrd.clone()),
+        ];
+
+        let (tx, rx) = pipe();
+        let tx_thread = std::thread::spawn(move || {
+            let files = files.into_iter().map(|(header, data)| (header, HostIo::new(Cursor::new(data))));
+            Xmodem::transmit_batch(files, rx, progress::noop)
+        });
+        let rx_thread = std::thread::spawn(move || {
+            let mut receiver = Xmodem::new(tx);
+            let mut received = vec![];
+            while let Some(header) = receiver.read_header()? {
+                let mut data = vec![];
+                let n = receiver.receive_into(HostIo::new(&mut data), header.size())?;
+                assert_eq!(n, data.len());
+                received.push((header, data));
+            }
+            Ok::<_, Error>(received)
+        });
+
+        assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 1800);
+        let received = rx_thread.join().expect("rx join okay").expect("rx okay");
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].0.name(), "kernel.bin");
+        assert_eq!(received[0].0.mtime(), Some(42));
+        assert_eq!(received[0].1, kernel);
+        assert_eq!(received[1].0.name(), "initrd");
+        assert_eq!(received[1].1, initrd);
+    }
+
+    #[test]
+    fn test_header_or_packet() {
+        let data: Vec<u8> = (0..1500).map(|i| (i % 253) as u8).collect();
+
+        // A plain XMODEM sender starts with packet 1.
+        let (tx, rx) = pipe();
+        let input = data.clone();
+        let tx_thread = std::thread::spawn(move || Xmodem::transmit(HostIo::new(Cursor::new(input)), rx));
+        let mut receiver = Xmodem::new(tx);
+        let mut packet = [0u8; 1024];
+        let n = match receiver.read_header_or_packet(&mut packet).expect("packet okay") {
+            Block::Packet(n) => n,
+            block => panic!("expected a packet, got {:?}", block),
+        };
+        let mut received = packet[..n].to_vec();
+        receiver.receive_into(HostIo::new(&mut received), None).expect("rest okay");
+        assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 1500);
+        assert_eq!(&received[..1500], &data[..]);
+        assert!(received[1500..].iter().all(|b| *b == 0));
+
+        // A YMODEM sender starts with a header.
+        let (tx, rx) = pipe();
+        let files = vec![(FileHeader::new("kernel.bin", Some(data.len()), None).unwrap(), data.clone())];
+        let tx_thread = std::thread::spawn(move || {
+            let files = files.into_iter().map(|(header, data)| (header, HostIo::new(Cursor::new(data))));
+            Xmodem::transmit_batch(files, rx, progress::noop)
+        });
+        let mut receiver = Xmodem::new(tx);
+        let header = match receiver.read_header_or_packet(&mut packet).expect("header okay") {
+            Block::Header(Some(header)) => header,
+            block => panic!("expected a header, got {:?}", block),
+        };
+        let mut received = vec![];
+        receiver.receive_into(HostIo::new(&mut received), header.size()).expect("file okay");
+        assert!(receiver.read_header().expect("end okay").is_none());
+        assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 1500);
+        assert_eq!(received, data);
+    }
+
+    #[test]
+    fn test_small_packet_eof_error() {
+        let mut xmodem = Xmodem::new(HostIo::new(Cursor::new(vec![NAK, NAK, NAK])));
+
+        let mut buffer = [1, 2, 3];
+        let e = xmodem.read_packet(&mut buffer[..]).expect_err("read EOF");
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+
+        let e = xmodem.write_packet(&buffer).expect_err("write EOF");
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_bad_control() {
+        let mut packet = [0; 128];
+        let e = Xmodem::new(HostIo::new(Cursor::new(vec![0, CAN, CAN])))
+            .read_packet(&mut packet[..])
+            .expect_err("CAN");
+
+        assert_eq!(e.kind(), ErrorKind::ConnectionAborted);
+
+        let mut port = Script::new(vec![Some(0xFF), Some(0x12), None].into());
+        let e = Xmodem::new(&mut port)
+            .read_packet(&mut packet[..])
+            .expect_err("bad contorl");
+
+        assert_eq!(e.kind(), ErrorKind::Interrupted);
+        assert!(port.0.is_empty());
+        assert_eq!(&port.1[..], &[CRC, NAK]);
+    }
+
+    #[test]
+    fn test_eot() {
+        let mut buffer = vec![NAK, 0, NAK, 0, ACK];
+        Xmodem::new(crate::io::Cursor::new(buffer.as_mut_slice()))
+            .write_packet(&[])
+            .expect("write empty buf for EOT");
+
+        assert_eq!(&buffer[..], &[NAK, EOT, NAK, EOT, ACK]);
+    }
+
+    #[test]
+    fn test_lossy_pipe_recovery() {
+        let input: Vec<u8> = (0..1000).map(|i| (i % 241) as u8).collect();
+        let expected = input.clone();
+
+        // Corrupt the first two attempts at the 1K packet by dropping a byte.
+        let (tx, rx) = pipe();
+        let lossy = Lossy { pipe: rx, written: 0, drops: vec![5, 1029 + 700] };
+        let tx_thread = std::thread::spawn(move || {
+            Xmodem::new_with_config(lossy, config(200), progress::noop)?.transmit_from(HostIo::new(&input[..]))
+        });
+        let rx_thread = std::thread::spawn(move || {
+            let mut output = vec![];
+            Xmodem::new_with_config(tx, config(20), progress::noop)?
+                .receive_into(HostIo::new(&mut output), Some(1000))
+                .map(|_| output)
+        });
+
+        assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 1000);
+        let output = rx_thread.join().expect("rx join okay").expect("rx okay");
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_resend_on_reply_timeout() {
+        let data = [7u8; 128];
+        let script = vec![Some(CRC), None, None, Some(ACK), Some(NAK), Some(ACK)];
+        let mut port = Script::new(script.into_iter().collect());
+        let n = Xmodem::new_with_config(&mut port, config(10), progress::noop)
+            .expect("config okay")
+            .transmit_from(HostIo::new(&data[..]))
+            .expect("transmit okay");
+
+        assert_eq!(n, 128);
+        assert_eq!(port.1.len(), 3 * (3 + 128 + 2) + 2);
+        assert!(port.1.chunks(3 + 128 + 2).take(3).all(|p| p[..3] == [SOH, 1, 255 - 1]));
+    }
+
+    #[test]
+    fn test_packet_retries_exhausted() {
+        let data = [7u8; 128];
+        let mut port = Script::new(vec![CRC, NAK, NAK, NAK, ACK].into_iter().map(Some).collect());
+        let config = XmodemConfig { packet_retries: 2, ..config(10) };
+        let e = Xmodem::new_with_config(&mut port, config, progress::noop)
+            .expect("config okay")
+            .transmit_from(HostIo::new(&data[..]))
+            .expect_err("every attempt rejected");
+
+        assert_eq!(e.kind(), ErrorKind::BrokenPipe);
+        assert_eq!(port.1.len(), 3 * (3 + 128 + 2));
+    }
+
+    #[test]
+    fn test_reject_on_packet_timeout() {
+        let data = [9u8; 128];
+        let crc = crc::crc16(&data);
+        let mut script: VecDeque<Option<u8>> = vec![SOH, 1, 255 - 1].into_iter().map(Some).collect();
+        script.extend(data[..10].iter().map(|b| Some(*b)));
+        script.push_back(None);
+        script.extend([SOH, 1, 255 - 1].iter().map(|b| Some(*b)));
+        script.extend(data.iter().map(|b| Some(*b)));
+        script.extend([(crc >> 8) as u8, crc as u8, EOT, EOT].iter().map(|b| Some(*b)));
+
+        let mut port = Script::new(script);
+        let mut output = vec![];
+        let n = Xmodem::new_with_config(&mut port, config(10), progress::noop)
+            .expect("config okay")
+            .receive_into(HostIo::new(&mut output), None)
+            .expect("receive okay");
+
+        assert_eq!(n, 128);
+        assert_eq!(&output[..], &data[..]);
+        assert_eq!(&port.1[..], &[CRC, NAK, ACK, NAK, ACK]);
+    }
+
+    #[test]
+    fn test_handshake_retries() {
+        let config = XmodemConfig { handshake_retries: 2, ..config(10) };
+        let mut port = Script::new(vec![None; 4].into());
+        let e = Xmodem::new_with_config(&mut port, config, progress::noop)
+            .expect("config okay")
+            .read_packet(&mut [0; 128])
+            .expect_err("sender never answers");
+
+        assert_eq!(e.kind(), ErrorKind::TimedOut);
+        assert_eq!(&port.1[..], &[CRC, CRC, NAK, NAK]);
+
+        let mut port = Script::new(vec![None, None, None, None, Some(NAK)].into());
+        let e = Xmodem::new_with_config(&mut port, config, progress::noop)
+            .expect("config okay")
+            .write_packet(&[0; 128])
+            .expect_err("receiver asks too late");
+
+        assert_eq!(e.kind(), ErrorKind::TimedOut);
+        assert!(port.1.is_empty());
+    }
+
+    #[test]
+    fn test_transfer_deadline() {
+        // A clock advancing by 100 milliseconds every time it is read.
+        fn clock() -> u64 {
+            static NOW: AtomicU64 = AtomicU64::new(0);
+            NOW.fetch_add(100, Ordering::SeqCst)
+        }
+
+        let data = [7u8; 384];
+        let mut port = Script::new(vec![CRC, ACK, ACK, ACK].into_iter().map(Some).collect());
+        let config = XmodemConfig { deadline: Some((250, clock)), ..config(10) };
+        let e = Xmodem::new_with_config(&mut port, config, progress::noop)
+            .expect("config okay")
+            .transmit_from(HostIo::new(&data[..]))
+            .expect_err("deadline passes");
+
+        assert_eq!(e.kind(), ErrorKind::TimedOut);
+        assert_eq!(port.1.len(), 2 * (3 + 128 + 2));
+    }
+
+    /// Returns the bytes of packet `number` carrying `data`, with a CRC-16.
+    fn crc_packet(number: u8, data: &[u8]) -> Vec<Option<u8>> {
+        let crc = crc::crc16(data);
+        let mut packet = vec![SOH, number, 255 - number];
+        packet.extend_from_slice(data);
+        packet.extend_from_slice(&[(crc >> 8) as u8, crc as u8]);
+        packet.into_iter().map(Some).collect()
+    }
+
+    #[test]
+    fn test_duplicate_packet() {
+        let (first, second) = ([1u8; 128], [2u8; 128]);
+        let mut script = crc_packet(1, &first);
+        script.extend(crc_packet(1, &first));
+        script.extend(crc_packet(2, &second));
+        script.extend(vec![Some(EOT), Some(EOT)]);
+
+        let mut port = Script::new(script.into());
+        let mut output = vec![];
+        let n = Xmodem::new(&mut port).receive_into(HostIo::new(&mut output), None).expect("receive okay");
+        assert_eq!(n, 256);
+        assert_eq!(&output[..128], &first[..]);
+        assert_eq!(&output[128..], &second[..]);
+        assert_eq!(&port.1[..], &[CRC, ACK, ACK, ACK, NAK, ACK]);
+    }
+
+    #[test]
+    fn test_resync_after_garbage() {
+        let data = [3u8; 128];
+        let mut script = vec![Some(0x7F), Some(0), Some(SOH), None];
+        script.extend(crc_packet(1, &data));
+        script.extend(vec![Some(0x55), None, Some(EOT), Some(EOT)]);
+
+        let mut port = Script::new(script.into());
+        let mut output = vec![];
+        let n = Xmodem::new(&mut port).receive_into(HostIo::new(&mut output), None).expect("receive okay");
+        assert_eq!(n, 128);
+        assert_eq!(&output[..], &data[..]);
+        assert_eq!(&port.1[..], &[CRC, NAK, ACK, NAK, NAK, ACK]);
+    }
+
+    #[test]
+    fn test_lost_ack() {
+        let input: Vec<u8> = (0..384).map(|i| i as u8).collect();
+        let expected = input.clone();
+
+        // Drop the receiver's ACK of the second packet: `C`, ACK, ACK.
+        let (tx, rx) = pipe();
+        let lossy = Lossy { pipe: tx, written: 0, drops: vec![2] };
+        let tx_thread = std::thread::spawn(move || {
+            Xmodem::new_with_config(rx, config(50), progress::noop)?.transmit_from(HostIo::new(&input[..]))
+        });
+        let rx_thread = std::thread::spawn(move || {
+            let mut output = vec![];
+            Xmodem::new_with_config(lossy, config(500), progress::noop)?
+                .receive_into(HostIo::new(&mut output), None)
+                .map(|_| output)
+        });
+
+        assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 384);
+        let output = rx_thread.join().expect("rx join okay").expect("rx okay");
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_progress_events() {
+        let data = [5u8; 200];
+        let header = FileHeader::new("data", Some(200), None).expect("valid header");
+        let script = vec![Some(CRC), Some(ACK), Some(CRC), Some(NAK), Some(ACK), None, Some(ACK),
+                          Some(NAK), Some(ACK), Some(CRC), Some(ACK)];
+        let mut port = Script::new(script.into());
+        let mut events = vec![];
+        let n = Xmodem::new_with_config(&mut port, config(10), |p| events.push(p))
+            .expect("config okay")
+            .transmit_files(vec![(header, HostIo::new(&data[..]))])
+            .expect("transmit okay");
+
+        assert_eq!(n, 200);
+        let header_events = [
+            Progress::Waiting,
+            Progress::Started,
+            Progress::Packet { number: 0, size: 128, offset: 128, total: None },
+        ];
+        assert_eq!(&events[..3], &header_events[..]);
+        assert_eq!(&events[3..10], &[
+            Progress::Waiting,
+            Progress::Started,
+            Progress::Retry { number: 1, reason: RetryReason::Rejected },
+            Progress::Packet { number: 1, size: 128, offset: 128, total: Some(200) },
+            Progress::Retry { number: 2, reason: RetryReason::TimedOut },
+            Progress::Packet { number: 2, size: 128, offset: 200, total: Some(200) },
+            Progress::Finished { bytes: 200 },
+        ][..]);
+        assert_eq!(&events[10..], &header_events[..]);
+
+        let mut port = Script::new(vec![Some(0x7F), None, Some(CAN), Some(CAN)].into());
+        let mut events = vec![];
+        let e = Xmodem::new_with_progress(&mut port, |p| events.push(p))
+            .receive_into(HostIo::new(io::sink()), None)
+            .expect_err("cancelled");
+
+        assert_eq!(e.kind(), ErrorKind::ConnectionAborted);
+        assert_eq!(&events[..], &[
+            Progress::Started,
+            Progress::Retry { number: 1, reason: RetryReason::Garbage },
+            Progress::Cancelled,
+        ][..]);
+
+        let mut events = vec![];
+        let e = Xmodem::new_with_config(Script::new(vec![None; 6].into()), config(10), |p| events.push(p))
+            .expect("config okay")
+            .receive_into(HostIo::new(io::sink()), None)
+            .expect_err("sender never answers");
+
+        assert_eq!(e.kind(), ErrorKind::TimedOut);
+        assert_eq!(&events[..], &[Progress::Started, Progress::Timeout][..]);
+    }
+
+    #[test]
+    fn test_streaming_batch() {
+        let kernel: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+        let expected = kernel.clone();
+        let streaming = XmodemConfig { streaming: true, ..config(100) };
+
+        let (tx, rx) = pipe();
+        let tx_thread = std::thread::spawn(move || {
+            let header = FileHeader::new("kernel.bin", Some(kernel.len()), None)?;
+            Xmodem::new_with_config(rx, streaming, progress::noop)?
+                .transmit_files(vec![(header, HostIo::new(Cursor::new(kernel)))])
+        });
+        let rx_thread = std::thread::spawn(move || {
+            let mut tx = tx;
+            let mut output = vec![];
+            let mut receiver = Xmodem::new_with_config(&mut tx, streaming, progress::noop)?;
+            let header = receiver.read_header()?.expect("a file");
+            receiver.receive_into(HostIo::new(&mut output), header.size())?;
+            assert!(receiver.read_header()?.is_none());
+            Ok::<_, Error>((output, tx.2))
+        });
+
+        assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 5000);
+        let (output, written) = rx_thread.join().expect("rx join okay").expect("rx okay");
+        assert_eq!(output, expected);
+        // Only the EOT is acknowledged.
+        assert_eq!(&written[..], &[STREAMING, STREAMING, NAK, ACK, STREAMING]);
+    }
+
+    #[test]
+    fn test_streaming_fallback() {
+        let streaming = XmodemConfig { streaming: true, ..config(10) };
+        let data = [3u8; 128];
+        let mut script = vec![None; 3];
+        script.extend(crc_packet(1, &data));
+        script.extend(vec![Some(EOT), Some(EOT)]);
+
+        let mut port = Script::new(script.into());
+        let mut output = vec![];
+        let n = Xmodem::new_with_config(&mut port, streaming, progress::noop)
+            .expect("config okay")
+            .receive_into(HostIo::new(&mut output), None)
+            .expect("receive okay");
+
+        assert_eq!(n, 128);
+        assert_eq!(&output[..], &data[..]);
+        assert_eq!(&port.1[..], &[STREAMING, STREAMING, STREAMING, CRC, ACK, NAK, ACK]);
+
+        let mut port = Script::new(vec![STREAMING, CRC, ACK, NAK, ACK].into_iter().map(Some).collect());
+        let n = Xmodem::new_with_config(&mut port, config(10), progress::noop)
+            .expect("config okay")
+            .transmit_from(HostIo::new(&data[..]))
+            .expect("transmit okay");
+
+        assert_eq!(n, 128);
+        let packet: Vec<u8> = crc_packet(1, &data).into_iter().map(Option::unwrap).collect();
+        assert_eq!(&port.1[..131 + 2], &packet[..]);
+    }
+
+    #[test]
+    fn test_streaming_cancel_on_error() {
+        let streaming = XmodemConfig { streaming: true, ..config(10) };
+        let mut script = crc_packet(1, &[9u8; 128]);
+        script[10] = Some(0);
+
+        let mut port = Script::new(script.into());
+        let e = Xmodem::new_with_config(&mut port, streaming, progress::noop)
+            .expect("config okay")
+            .receive_into(HostIo::new(io::sink()), None)
+            .expect_err("corrupted packet");
+
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert_eq!(&port.1[..], &[STREAMING, CAN, CAN]);
+
+        // The receiver cancels after the first of three packets.
+        let mut port = Script::new(vec![Some(STREAMING), None, Some(CAN), Some(CAN)].into());
+        let mut events = vec![];
+        let e = Xmodem::new_with_config(&mut port, streaming, |p| events.push(p))
+            .expect("config okay")
+            .transmit_from(HostIo::new(&[1u8; 384][..]))
+            .expect_err("receiver cancelled");
+
+        assert_eq!(e.kind(), ErrorKind::ConnectionAborted);
+        let packet: Vec<u8> = crc_packet(1, &[1u8; 128]).into_iter().map(Option::unwrap).collect();
+        assert_eq!(port.1, packet);
+        assert_eq!(events.last(), Some(&Progress::Cancelled));
+    }
+
+    /// Transfers `input` over a channel hit by `faults` on the schedule of
+    /// `seed`, and returns the sender's and the receiver's results. Panics if
+    /// either side hangs.
+    fn noisy_transfer(seed: u64, faults: Faults, input: &[u8], streaming: bool)
+        -> (Result<usize, Error>, Result<Vec<u8>, Error>)
+    {
+        let (tx, rx) = channel::channel(seed, faults);
+        let size = input.len();
+        let sender = channel::transmit(rx, XmodemConfig { streaming, ..config(100) }, input.to_vec());
+        let (rx_done, rx_result) = channel();
+        let rx_config = XmodemConfig { streaming, ..config(20) };
+        std::thread::spawn(move || {
+            let mut output = vec![];
+            let result = Xmodem::new_with_config(tx, rx_config, progress::noop)
+                .and_then(|mut receiver| receiver.receive_into(HostIo::new(&mut output), Some(size)))
+                .map(|_| output);
+            rx_done.send(result).expect("send result");
+        });
+
+        let received = rx_result.recv_timeout(Duration::from_secs(30)).expect("receiver hangs");
+        (sender.result(), received)
+    }
+
+    /// Returns a random mix of faults for `rng`, hitting up to 1 byte in 1000.
+    fn random_faults(rng: &mut Rng) -> Faults {
+        Faults {
+            drop: rng.below(300) as u32,
+            flip: rng.below(300) as u32,
+            duplicate: rng.below(300) as u32,
+            delay: rng.below(100) as u32,
+            delay_ms: 40,
+        }
+    }
+
+    #[test]
+    fn test_channel_schedule() {
+        let faults = Faults { flip: 100_000, drop: 100_000, duplicate: 100_000, ..Faults::default() };
+        let received = |seed| {
+            let (mut a, mut b) = channel::channel(seed, faults);
+            a.write(&[0x55; 1000]).expect("write okay");
+            drop(a);
+            let mut bytes = vec![];
+            while let Ok(byte) = b.read_byte() {
+                bytes.push(byte);
+            }
+            bytes
+        };
+
+        let bytes = received(7);
+        assert_eq!(bytes, received(7));
+        assert_ne!(bytes, received(8));
+        assert!(bytes.iter().any(|b| *b != 0x55));
+        assert_ne!(bytes.len(), 1000);
+    }
+
+    /// Runs the noisy transfer of `seed`, with random faults and input, and
+    /// returns whether it was delivered. Checks that the data either arrives
+    /// intact or both sides report an error: a sender reporting success while
+    /// the receiver failed, or the other way around, would go unnoticed.
+    fn check_noisy_transfer(seed: u64, streaming: bool) -> bool {
+        let mut rng = Rng::new(seed);
+        let faults = random_faults(&mut rng);
+        let input: Vec<u8> = (0..1 + rng.below(4000)).map(|_| rng.next_u64() as u8).collect();
+
+        match noisy_transfer(seed, faults, &input, streaming) {
+            (Ok(n), Ok(output)) => {
+                assert_eq!(n, input.len(), "seed {}", seed);
+                assert!(output == input, "seed {}: corrupted delivery with {:?}", seed, faults);
+                true
+            }
+            (Err(_), Err(_)) => false,
+            (sent, received) => {
+                let received = received.map(|output| output.len());
+                panic!("seed {}: the sides disagree with {:?}: sent {:?}, received {:?}", seed, faults, sent, received)
+            }
+        }
+    }
+
+    #[test]
+    fn test_noisy_transfers() {
+        // The faults are light enough for retries to get every file through.
+        for seed in 0..32 {
+            assert!(check_noisy_transfer(seed, false), "seed {}: not delivered", seed);
+        }
+    }
+
+    #[test]
+    fn test_noisy_streaming_transfers() {
+        // Any fault hitting a packet cancels a streamed transfer, so whether
+        // a seed gets through depends on the timing of the delays.
+        for seed in 0..32 {
+            check_noisy_transfer(seed, true);
+        }
+
+        let input = [0xA5u8; 3000];
+        let (sent, received) = noisy_transfer(0, Faults::default(), &input, true);
+        assert_eq!(sent, Ok(3000));
+        assert_eq!(received.expect("clean line"), &input[..]);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut port = Script::new(vec![Some(ACK), Some(0x42), None].into());
+        let mut events = vec![];
+        Xmodem::new_with_progress(&mut port, |p| events.push(p)).cancel().expect("cancel okay");
+        assert_eq!(&port.1[..], &[CAN, CAN]);
+        assert!(port.0.is_empty());
+        assert_eq!(&events[..], &[Progress::Cancelled][..]);
+
+        let (tx, rx) = pipe();
+        let tx_thread = std::thread::spawn(move || {
+            let mut sender = Xmodem::new_with_config(rx, config(50), progress::noop)?;
+            sender.write_packet(&[1; 128])?;
+            sender.cancel()
+        });
+        let rx_thread = std::thread::spawn(move || {
+            Xmodem::new_with_config(tx, config(1000), progress::noop)?
+                .receive_into(HostIo::new(io::sink()), None)
+        });
+
+        tx_thread.join().expect("tx join okay").expect("tx okay");
+        let e = rx_thread.join().expect("rx join okay").expect_err("cancelled");
+        assert_eq!(e.kind(), ErrorKind::ConnectionAborted);
+    }
+
+    #[test]
+    fn test_lone_can_is_noise() {
+        let data = [5u8; 128];
+        let mut script = vec![Some(CAN)];
+        script.extend(crc_packet(1, &data));
+        script.extend(vec![Some(EOT), Some(EOT)]);
+
+        let mut port = Script::new(script.into());
+        let mut output = vec![];
+        let n = Xmodem::new(&mut port).receive_into(HostIo::new(&mut output), None).expect("receive okay");
+        assert_eq!(n, 128);
+        assert_eq!(&output[..], &data[..]);
+
+        let mut port = Script::new(vec![CRC, CAN, ACK, NAK, CAN, ACK].into_iter().map(Some).collect());
+        let n = Xmodem::new(&mut port).transmit_from(HostIo::new(&data[..])).expect("transmit okay");
+        assert_eq!(n, 128);
     }
 }
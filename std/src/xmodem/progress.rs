@@ -1,26 +1,58 @@
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum Progress {
-    /// Waiting for receiver to send NAK.
+    /// Waiting for receiver to send NAK or C.
     Waiting,
     /// Download/upload has started.
     Started,
-    /// Packet `.0` was transmitted/received.
-    Packet(u8),
+    /// Packet `number` carrying `size` bytes was transmitted/received. The
+    /// first `offset` bytes of the file, out of `total` if known, have been
+    /// transferred.
+    Packet { number: u8, size: usize, offset: usize, total: Option<usize> },
+    /// Packet `number` failed because of `reason`. It is sent or requested
+    /// again unless the retries are exhausted.
+    Retry { number: u8, reason: RetryReason },
+    /// The transfer was abandoned because the other side didn't answer in
+    /// time or the transfer deadline passed.
+    Timeout,
+    /// The transfer was cancelled with `CAN`.
+    Cancelled,
+    /// The transfer of a file of `bytes` bytes is complete.
+    Finished { bytes: usize },
 }
 
 impl Clone for Progress {
     fn clone(&self) -> Progress{
-        match self {
-            Progress::Waiting => Progress::Waiting,
-            Progress::Started => Progress::Started,
-            Progress::Packet(id) => Progress::Packet(*id),
-        }
+        *self
     }
 }
 
 impl Copy for Progress {}
 
-/// Type for progress callbacks.
+/// The reason a packet is sent or requested again.
+#[derive(Debug, PartialEq)]
+pub enum RetryReason {
+    /// The receiver rejected the packet with a `NAK`.
+    Rejected,
+    /// The packet's checksum or CRC didn't match its data.
+    Corrupted,
+    /// The other side didn't answer before the inner stream timed out.
+    TimedOut,
+    /// The sender sent the previous packet again after a lost `ACK`.
+    Duplicate,
+    /// The receiver read garbage instead of the start of a packet.
+    Garbage,
+}
+
+impl Clone for RetryReason {
+    fn clone(&self) -> RetryReason {
+        *self
+    }
+}
+
+impl Copy for RetryReason {}
+
+/// Type for progress callbacks that don't keep any state. Any `FnMut` closure
+/// can be used as a progress callback as well.
 pub type ProgressFn = fn(Progress);
 
 /// Noop progress callback.
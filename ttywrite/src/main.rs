@@ -4,33 +4,47 @@
 #[macro_use] extern crate structopt_derive;
 
 use std::env;
-use std::time::Instant;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::cmp;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
 use structopt::StructOpt;
 use serial::core::{CharSize, BaudRate, StopBits, FlowControl, SerialDevice, SerialPortSettings};
-use xmodem::{Xmodem, Progress};
+use swift_std::xmodem::{Xmodem, XmodemConfig, Progress, FileHeader};
+use swift_std::io::host::{HostIo, HostTimeout};
 
 mod parsers;
-mod xmodem;
 
 use parsers::{parse_width, parse_stop_bits, parse_flow_control, parse_baud_rate};
 
 #[derive(StructOpt, Debug)]
 #[structopt(about = "Write to TTY using the XMODEM protocol by default.")]
 struct Opt {
-    #[structopt(short = "i", long = "input", help = "Input file (defaults to stdin if not set)", parse(from_os_str))]
-    input: Option<PathBuf>,
+    #[structopt(short = "i", long = "input", help = "Input file, may be repeated (defaults to stdin if not set)",
+                parse(from_os_str), number_of_values_raw = "1")]
+    input: Vec<PathBuf>,
 
     #[structopt(short = "b", long = "baud", parse(try_from_str = "parse_baud_rate"),
                 help = "Set baud rate", default_value = "115200")]
     baud_rate: BaudRate,
 
     #[structopt(short = "t", long = "timeout", parse(try_from_str),
-                help = "Set timeout in seconds", default_value = "10")]
+                help = "Set read timeout in seconds", default_value = "10")]
     timeout: u64,
 
+    #[structopt(short = "R", long = "retries", parse(try_from_str),
+                help = "Set number of times a packet is sent again", default_value = "10")]
+    retries: usize,
+
+    #[structopt(short = "d", long = "deadline", parse(try_from_str),
+                help = "Abort the transfer after this many seconds")]
+    deadline: Option<u64>,
+
     #[structopt(short = "w", long = "width", parse(try_from_str = "parse_width"),
                 help = "Set data character width in bits", default_value = "8")]
     char_width: CharSize,
@@ -48,34 +62,151 @@ struct Opt {
 
     #[structopt(short = "r", long = "raw", help = "Disable XMODEM")]
     raw: bool,
+
+    #[structopt(short = "y", long = "ymodem", help = "Send the input files as a YMODEM batch instead of with XMODEM")]
+    ymodem: bool,
+
+    #[structopt(short = "n", long = "no-streaming", help = "Wait for an ACK after each packet even if the receiver can stream")]
+    no_streaming: bool,
 }
 
-fn progress_fn(_progress: Progress) {
-    static mut LAST_TIME: Option<Instant> = None;
-    static mut BYTES_SENT: u64 = 0;
-    unsafe {
-        BYTES_SENT += 128;
-        LAST_TIME = match LAST_TIME {
-            Some(last_time) => {
-                let now = Instant::now();
+/// Returns a progress callback printing the offset and transfer rate of each
+/// packet, and any retry or failure.
+fn progress_printer() -> impl FnMut(Progress) {
+    let mut last_time: Option<Instant> = None;
+    move |progress| match progress {
+        Progress::Waiting => println!("Waiting for the receiver..."),
+        Progress::Started => {}
+        Progress::Packet { size, offset, total, .. } => {
+            let now = Instant::now();
+            if let Some(last_time) = last_time.replace(now) {
                 let duration = now - last_time;
                 let nanos = duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64;
+                let total = total.map(|total| format!(" of {}", total)).unwrap_or_default();
                 println!(
-                    "Progress: {} bytes sent at {:.2} KiB/s",
-                    BYTES_SENT,
-                    128.0 * 1_000_000_000.0 / 1024.0 / nanos as f64
+                    "Progress: {}{} bytes sent at {:.2} KiB/s",
+                    offset,
+                    total,
+                    size as f64 * 1_000_000_000.0 / 1024.0 / nanos as f64
                 );
-                Some(now)
             }
-            None => Some(Instant::now()),
-        };
+        }
+        Progress::Retry { number, reason } => eprintln!("Packet {} sent again: {:?}", number, reason),
+        Progress::Timeout => eprintln!("Timed out"),
+        Progress::Cancelled => eprintln!("Transfer cancelled"),
+        Progress::Finished { bytes } => println!("Sent {} bytes", bytes),
     }
 }
 
-fn main() {
-    use std::fs::File;
-    use std::io::{self, BufReader};
+/// Returns the milliseconds elapsed since the Unix epoch.
+fn clock() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("clock should be past the epoch");
+    now.as_secs() * 1000 + now.subsec_millis() as u64
+}
+
+/// Set by Ctrl-C. Accesses to the serial port then fail until the transfer
+/// is cancelled.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Set once the transfer failed and is being cancelled. Accesses to the
+/// serial port work again, and reads wait no longer than `POLL`.
+static CANCELLING: AtomicBool = AtomicBool::new(false);
+
+/// How long a read waits before checking for Ctrl-C again.
+const POLL: Duration = Duration::from_millis(100);
+
+/// Returns an error if Ctrl-C was pressed and the transfer isn't cancelled
+/// yet.
+fn check_interrupted() -> io::Result<()> {
+    if INTERRUPTED.load(Ordering::SeqCst) && !CANCELLING.load(Ordering::SeqCst) {
+        return Err(io::Error::new(io::ErrorKind::Other, "interrupted"));
+    }
+    Ok(())
+}
+
+/// The serial port, with the read timeout set by the transfer.
+struct Serial<'a> {
+    port: &'a mut serial::SystemPort,
+    timeout: Duration,
+}
+
+impl<'a> Read for Serial<'a> {
+    /// Reads from the port in slices of at most `POLL`, so that Ctrl-C stops
+    /// a read that is waiting.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let timeout = if CANCELLING.load(Ordering::SeqCst) { cmp::min(self.timeout, POLL) } else { self.timeout };
+        let start = Instant::now();
+        loop {
+            check_interrupted()?;
+            let left = timeout.checked_sub(start.elapsed()).unwrap_or_default();
+            SerialDevice::set_timeout(self.port, cmp::min(left, POLL))?;
+            match self.port.read(buf) {
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut && start.elapsed() < timeout => continue,
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<'a> Write for Serial<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        check_interrupted()?;
+        self.port.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        check_interrupted()?;
+        self.port.flush()
+    }
+}
+
+impl<'a> HostTimeout for Serial<'a> {
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+}
+
+/// Returns the concatenation of the files in `paths`, or stdin if there are
+/// none.
+fn input(paths: &[PathBuf]) -> Box<dyn Read> {
+    if paths.is_empty() {
+        return Box::new(io::stdin());
+    }
+
+    paths.iter().fold(Box::new(io::empty()), |input, path| {
+        let file = File::open(path).expect("file should exist");
+        Box::new(input.chain(BufReader::new(file)))
+    })
+}
 
+/// Returns a YMODEM header and a reader for each file in `paths`, or for
+/// stdin if there are none. Stdin is read to its end first so that its exact
+/// size can be announced.
+fn files(paths: &[PathBuf]) -> Vec<(FileHeader, Box<dyn Read>)> {
+    if paths.is_empty() {
+        let mut data = vec![];
+        io::stdin().read_to_end(&mut data).expect("stdin should be readable");
+        let header = FileHeader::new("stdin", Some(data.len()), None).expect("header should be valid");
+        return vec![(header, Box::new(io::Cursor::new(data)))];
+    }
+
+    paths.iter().map(|path| {
+        let file = File::open(path).expect("file should exist");
+        let metadata = file.metadata().expect("file metadata should be readable");
+        let mtime = metadata.modified().ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs());
+        let name = path.file_name()
+            .and_then(|name| name.to_str())
+            .expect("file name should be valid UTF-8");
+        let header = FileHeader::new(name, Some(metadata.len() as usize), mtime)
+            .expect("file name should fit in a YMODEM header");
+        (header, Box::new(BufReader::new(file)) as Box<dyn Read>)
+    }).collect()
+}
+
+fn main() {
     let opt = Opt::from_args();
     let mut serial = serial::open(&opt.tty_path).expect("path points to invalid TTY");
 
@@ -95,33 +226,39 @@ fn main() {
         .expect("timeout should be valid");
 
     if opt.raw {
-        match opt.input {
-            Some(ref path) => {
-                let mut input = BufReader::new(File::open(path).expect("file should exist"));
-                io::copy(&mut input, &mut serial).expect("io transfer should succeed");
-            }
-            None => {
-                let mut input = io::stdin();
-                io::copy(&mut input, &mut serial).expect("io transfer should succeed");
-            }
-        };
+        io::copy(&mut input(&opt.input), &mut serial).expect("io transfer should succeed");
+        return;
+    }
+
+    let config = XmodemConfig {
+        packet_retries: opt.retries,
+        streaming: !opt.no_streaming,
+        byte_timeout: (opt.timeout * 1000) as u32,
+        deadline: opt.deadline.map(|secs| (secs * 1000, clock as fn() -> u64)),
+        ..XmodemConfig::default()
+    };
+    ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst))
+        .expect("Ctrl-C handler should be installed");
+    let port = HostIo::new(Serial { port: &mut serial, timeout: Duration::from_secs(opt.timeout) });
+    let mut transmitter = Xmodem::new_with_config(port, config, progress_printer())
+        .expect("timeout should be valid");
+    let result = if opt.ymodem {
+        let files = files(&opt.input).into_iter().map(|(header, file)| (header, HostIo::new(file)));
+        transmitter.transmit_files(files)
     } else {
-        // XMODEM
-        match opt.input {
-            Some(ref path) => {
-                let mut input = BufReader::new(File::open(path).expect("file should exist"));
-                match Xmodem::transmit_with_progress(input, &mut serial, progress_fn) {
-                    Ok(_) => return,
-                    Err(err) => panic!("Error: {:?}", err),
-                }
-            }
-            None => {
-                let mut input = io::stdin();
-                match Xmodem::transmit_with_progress(input, &mut serial, progress_fn) {
-                    Ok(_) => return,
-                    Err(err) => panic!("Error: {:?}", err),
-                }
-            }
+        transmitter.transmit_from(HostIo::new(input(&opt.input)))
+    };
+
+    if let Err(err) = result {
+        let interrupted = INTERRUPTED.load(Ordering::SeqCst);
+        if !interrupted {
+            eprintln!("Error: {}", io::Error::from(err));
+        }
+        // Tell the receiver to stop waiting for packets.
+        CANCELLING.store(true, Ordering::SeqCst);
+        if let Err(err) = transmitter.cancel() {
+            eprintln!("Error: failed to cancel the transfer: {}", io::Error::from(err));
         }
+        process::exit(if interrupted { 130 } else { 1 });
     }
 }